@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use owning_key::KeyId;
+use std::{
+    hint::black_box,
+    sync::Barrier,
+    thread,
+    time::{Duration, Instant},
+};
+
+fn key_id_new(c: &mut Criterion) {
+    c.bench_function("KeyId::new", |b| b.iter(KeyId::new));
+
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .max(4);
+    c.bench_function(&format!("KeyId::new x{threads} threads"), |b| {
+        b.iter_custom(|iters| {
+            let barrier = Barrier::new(threads);
+            thread::scope(|s| {
+                let handles: Vec<_> = (0..threads)
+                    .map(|_| {
+                        s.spawn(|| {
+                            barrier.wait();
+                            let start = Instant::now();
+                            for _ in 0..iters {
+                                black_box(KeyId::new());
+                            }
+                            start.elapsed()
+                        })
+                    })
+                    .collect();
+                let total: Duration = handles.into_iter().map(|h| h.join().unwrap()).sum();
+                total / threads as u32
+            })
+        })
+    });
+}
+
+criterion_group!(benches, key_id_new);
+criterion_main!(benches);
@@ -0,0 +1,135 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, Ident, Index, Member,
+    Result,
+};
+
+/// Derives `Locked` for a struct whose fields all implement `Locked`.
+///
+/// Alongside the impl, this generates a struct with the same shape whose
+/// fields are the corresponding `Unlocked` types, which is used as the
+/// `Unlocked` type of the derived impl. It is named `{Name}Unlocked` by
+/// default; use `#[locked(unlocked = OtherName)]` to override this.
+///
+/// Each field is locked and unlocked with the same key, and the `key_id()` of
+/// the struct is the `key_id()` of its first field.
+#[proc_macro_derive(Locked, attributes(locked))]
+pub fn derive_locked(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn unlocked_name(input: &DeriveInput) -> Result<Ident> {
+    let mut name = None;
+    for attr in &input.attrs {
+        if !attr.path().is_ident("locked") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("unlocked") {
+                name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported `locked` attribute"))
+            }
+        })?;
+    }
+    Ok(name.unwrap_or_else(|| format_ident!("{}Unlocked", input.ident)))
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let data = match &input.data {
+        Data::Struct(data) => data,
+        _ => {
+            return Err(Error::new(
+                Span::call_site(),
+                "`Locked` can only be derived for structs",
+            ))
+        }
+    };
+    if data.fields.is_empty() {
+        return Err(Error::new(
+            data.fields.span(),
+            "`Locked` cannot be derived for a struct without fields",
+        ));
+    }
+
+    let krate = quote!(::owning_key);
+    let name = &input.ident;
+    let unlocked = unlocked_name(&input)?;
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let generics = &input.generics;
+
+    let members: Vec<Member> = data
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        })
+        .collect();
+    let first = &members[0];
+
+    let unlocked_fields = data.fields.iter().map(|field| {
+        let field_vis = &field.vis;
+        let ty = &field.ty;
+        let ident = field.ident.as_ref().map(|ident| quote!(#ident:));
+        quote!(#field_vis #ident <#ty as #krate::Locked>::Unlocked)
+    });
+    let unlocked_def = match &data.fields {
+        Fields::Named(_) => quote! {
+            #vis struct #unlocked #generics #where_clause {
+                #(#unlocked_fields,)*
+            }
+        },
+        _ => quote! {
+            #vis struct #unlocked #generics (#(#unlocked_fields,)*) #where_clause;
+        },
+    };
+
+    Ok(quote! {
+        #unlocked_def
+
+        impl #impl_generics #krate::Locked for #name #ty_generics #where_clause {
+            type Unlocked = #unlocked #ty_generics;
+
+            #[inline]
+            fn key_id(&self) -> #krate::KeyId {
+                #krate::Locked::key_id(&self.#first)
+            }
+
+            #[inline]
+            unsafe fn raw_lock<__K: ?Sized + #krate::Key>(
+                value: Self::Unlocked,
+                key: &__K,
+            ) -> Self {
+                Self {
+                    #(#members: unsafe { #krate::Locked::raw_lock(value.#members, key) },)*
+                }
+            }
+
+            #[inline]
+            unsafe fn raw_unlock<__K: ?Sized + #krate::Key>(
+                self,
+                key: &mut __K,
+            ) -> Self::Unlocked {
+                #unlocked {
+                    #(#members: unsafe { #krate::Locked::raw_unlock(self.#members, key) },)*
+                }
+            }
+
+            #[inline]
+            unsafe fn raw_clone(&self) -> Self {
+                Self {
+                    #(#members: unsafe { #krate::Locked::raw_clone(&self.#members) },)*
+                }
+            }
+        }
+    })
+}
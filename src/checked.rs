@@ -0,0 +1,153 @@
+use crate::{Key, KeyId};
+use core::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr,
+};
+
+#[cfg(debug_assertions)]
+use alloc::vec::Vec;
+#[cfg(debug_assertions)]
+use std::sync::Mutex;
+
+/// The ids of all live [`CheckedKey`]s.
+#[cfg(debug_assertions)]
+static LIVE_IDS: Mutex<Vec<KeyId>> = Mutex::new(Vec::new());
+
+/// A wrapper around a key that, in debug builds, checks that no two live
+/// `CheckedKey`s ever return the same [`KeyId`], and that the wrapped key
+/// always returns the same [`KeyId`].
+///
+/// This is meant to help catch custom [`Key`] implementations that violate the
+/// uniqueness invariant. It can only detect violations between keys that are
+/// both wrapped in a `CheckedKey`, and it performs no checks in release builds.
+#[derive(Debug)]
+pub struct CheckedKey<K> {
+    inner: K,
+    id: KeyId,
+}
+
+unsafe impl<K: Key> Key for CheckedKey<K> {
+    #[inline]
+    fn id(&self) -> KeyId {
+        let id = self.inner.id();
+        debug_assert_eq!(id, self.id, "wrapped key returned a different KeyId");
+        id
+    }
+}
+
+impl<K> Deref for CheckedKey<K> {
+    type Target = K;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<K> DerefMut for CheckedKey<K> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<K> Drop for CheckedKey<K> {
+    #[inline]
+    fn drop(&mut self) {
+        unregister(self.id);
+    }
+}
+
+#[inline]
+fn unregister(id: KeyId) {
+    #[cfg(debug_assertions)]
+    {
+        let mut live_ids = LIVE_IDS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(index) = live_ids.iter().position(|&live_id| live_id == id) {
+            live_ids.swap_remove(index);
+        }
+    }
+    #[cfg(not(debug_assertions))]
+    let _ = id;
+}
+
+impl<K: Key> CheckedKey<K> {
+    /// # Panics
+    ///
+    /// In debug builds, panics if another live `CheckedKey` has the same
+    /// [`KeyId`] as `inner`.
+    #[inline]
+    pub fn new(inner: K) -> Self {
+        let id = inner.id();
+        #[cfg(debug_assertions)]
+        {
+            let mut live_ids = LIVE_IDS.lock().unwrap_or_else(|e| e.into_inner());
+            if live_ids.contains(&id) {
+                drop(live_ids);
+                panic!("two live keys have the same KeyId: {id:?}");
+            }
+            live_ids.push(id);
+        }
+        Self { inner, id }
+    }
+}
+
+impl<K> CheckedKey<K> {
+    #[inline]
+    pub fn into_inner(self) -> K {
+        let this = ManuallyDrop::new(self);
+        unregister(this.id);
+        unsafe { ptr::read(&this.inner) }
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// A broken key that always returns the same id. Each test uses its own
+    /// id, since the live ids are shared between tests.
+    struct ConstKey(usize);
+
+    unsafe impl Key for ConstKey {
+        fn id(&self) -> KeyId {
+            KeyId { id: self.0 }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "two live keys have the same KeyId")]
+    fn same_id_panics() {
+        let _a = ConstKey(usize::MAX - 1).debug_assert_unique();
+        let _b = ConstKey(usize::MAX - 1).debug_assert_unique();
+    }
+
+    #[test]
+    fn same_id_after_drop() {
+        let a = CheckedKey::new(ConstKey(usize::MAX - 2));
+        drop(a);
+        let b = CheckedKey::new(ConstKey(usize::MAX - 2));
+        let _ = b.into_inner();
+        let _c = CheckedKey::new(ConstKey(usize::MAX - 2));
+    }
+
+    /// A broken key that returns a new id each time.
+    struct CountingKey(Cell<usize>);
+
+    unsafe impl Key for CountingKey {
+        fn id(&self) -> KeyId {
+            let id = self.0.get();
+            self.0.set(id - 1);
+            KeyId { id }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "wrapped key returned a different KeyId")]
+    fn changing_id_panics() {
+        let key = CheckedKey::new(CountingKey(Cell::new(usize::MAX - 3)));
+        key.id();
+    }
+}
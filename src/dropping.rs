@@ -0,0 +1,833 @@
+use crate::{locked::check_id, ForgettingKey, Key, KeyId, Locked, LockedVec};
+use alloc::{
+    boxed::Box,
+    vec::{self, Vec},
+};
+use core::{
+    borrow::Borrow,
+    cell::RefCell,
+    cmp::Reverse,
+    fmt::{self, Debug, Formatter},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ops::Deref,
+    ptr::NonNull,
+};
+use std::{
+    collections::HashSet,
+    sync::{Mutex, PoisonError},
+};
+
+#[cfg(feature = "serde")]
+use crate::SerializeLocked;
+#[cfg(feature = "serde")]
+use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
+
+union DropperInner<T> {
+    value: ManuallyDrop<T>,
+    _pad: u8,
+}
+
+#[cfg(feature = "serde")]
+type SerializeFn =
+    unsafe fn(NonNull<()>, &ForgettingKey, &mut dyn FnMut(&dyn erased_serde::Serialize));
+
+struct Dropper<'a> {
+    ptr: NonNull<()>,
+    seq: u64,
+    unlock_drop: unsafe fn(NonNull<()>, &mut ForgettingKey),
+    free: unsafe fn(NonNull<()>),
+    #[cfg(feature = "serde")]
+    serialize: Option<SerializeFn>,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl PartialEq for Dropper<'_> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr.eq(&other.ptr)
+    }
+}
+
+impl Eq for Dropper<'_> {}
+
+impl Debug for Dropper<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dropper")
+            .field("ptr", &self.ptr)
+            .field("unlock_drop", &(self.unlock_drop as *const ()))
+            .field("_marker", &self._marker)
+            .finish()
+    }
+}
+
+impl Hash for Dropper<'_> {
+    #[inline]
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: Hasher,
+    {
+        self.ptr.hash(state);
+    }
+}
+
+impl Borrow<NonNull<()>> for Dropper<'_> {
+    #[inline]
+    fn borrow(&self) -> &NonNull<()> {
+        &self.ptr
+    }
+}
+
+impl<'a> Dropper<'a> {
+    #[inline]
+    fn new<T: Locked + 'a>(value: T) -> Self {
+        let value = ManuallyDrop::new(value);
+        let dropper = Box::new(DropperInner { value });
+        let ptr = NonNull::new(Box::into_raw(dropper)).unwrap();
+        Self {
+            ptr: ptr.cast(),
+            seq: 0,
+            unlock_drop: |ptr, key| {
+                let ptr: NonNull<DropperInner<T>> = ptr.cast();
+                let mut dropper = unsafe { Box::from_raw(ptr.as_ptr()) };
+                let value = unsafe { ManuallyDrop::take(&mut dropper.value) };
+                drop(key.unlock(value));
+            },
+            free: |ptr| {
+                let ptr: NonNull<DropperInner<T>> = ptr.cast();
+                drop(unsafe { Box::from_raw(ptr.as_ptr()) });
+            },
+            #[cfg(feature = "serde")]
+            serialize: None,
+            _marker: PhantomData,
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[inline]
+    fn new_serializable<T: SerializeLocked + 'a>(value: T) -> Self {
+        struct Keyed<'b, T> {
+            value: &'b T,
+            key: &'b ForgettingKey,
+        }
+        impl<T: SerializeLocked> Serialize for Keyed<'_, T> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.value.serialize_locked(self.key, serializer)
+            }
+        }
+        Self {
+            serialize: Some(|ptr, key, f| {
+                let ptr: NonNull<DropperInner<T>> = ptr.cast();
+                let value = unsafe { &*ptr.as_ref().value };
+                f(&Keyed { value, key });
+            }),
+            ..Self::new(value)
+        }
+    }
+
+    #[inline]
+    fn unlock_drop(self, key: &mut ForgettingKey) {
+        unsafe { (self.unlock_drop)(self.ptr, key) };
+    }
+
+    /// Frees the copy of the value without dropping it, after the original
+    /// has been unlocked.
+    #[inline]
+    fn free(self) {
+        unsafe { (self.free)(self.ptr) };
+    }
+}
+
+/// The droppers tracked by a key, which remember the order they were inserted
+/// in.
+#[derive(Debug, Default)]
+struct Droppers<'a> {
+    set: HashSet<Dropper<'a>>,
+    next_seq: u64,
+}
+
+impl<'a> Droppers<'a> {
+    #[inline]
+    fn reserve(&mut self, additional: usize) {
+        self.set.reserve(additional);
+    }
+
+    #[inline]
+    fn insert(&mut self, mut dropper: Dropper<'a>) -> bool {
+        dropper.seq = self.next_seq;
+        self.next_seq += 1;
+        self.set.insert(dropper)
+    }
+
+    #[inline]
+    fn take(&mut self, ptr: &NonNull<()>) -> Option<Dropper<'a>> {
+        self.set.take(ptr)
+    }
+
+    /// Returns all droppers in the order they were inserted in.
+    #[cfg(feature = "serde")]
+    #[inline]
+    fn iter_fifo(&self) -> vec::IntoIter<&Dropper<'a>> {
+        let mut droppers: Vec<_> = self.set.iter().collect();
+        droppers.sort_unstable_by_key(|dropper| dropper.seq);
+        droppers.into_iter()
+    }
+
+    /// Removes all droppers, returning them in the reverse of the order they
+    /// were inserted in.
+    #[inline]
+    fn take_lifo(&mut self) -> vec::IntoIter<Dropper<'a>> {
+        let mut droppers: Vec<_> = mem::take(&mut self.set).into_iter().collect();
+        droppers.sort_unstable_by_key(|dropper| Reverse(dropper.seq));
+        droppers.into_iter()
+    }
+}
+
+/// A value locked by a [`LocalDroppingKey`] or [`DroppingKey`], which the key
+/// will unlock and drop if it is not unlocked first.
+///
+/// The key keeps its own copy of the locked value, so this only implements
+/// [`Deref`]. Swapping or replacing the value through a `&mut` would leave
+/// that copy tracking an allocation the key no longer owns, which would be
+/// freed twice:
+///
+/// ```compile_fail,E0596
+/// # use owning_key::{LocalDroppingKey, LockedBox};
+/// let key = LocalDroppingKey::new();
+/// let mut a = key.lock::<LockedBox<i32>>(Box::new(1));
+/// let mut b = key.lock::<LockedBox<i32>>(Box::new(2));
+/// std::mem::swap(&mut *a, &mut *b);
+/// ```
+#[derive(Debug)]
+pub struct Dropping<T> {
+    value: T,
+    ptr: NonNull<()>,
+}
+
+unsafe impl<T: Send> Send for Dropping<T> {}
+
+unsafe impl<T: Sync> Sync for Dropping<T> {}
+
+impl<T> Deref for Dropping<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+fn unlock_drop_all<'a, I: Iterator<Item = Dropper<'a>>>(droppers: I, key: &mut ForgettingKey) {
+    struct DropGuard<'a, 'b, I: Iterator<Item = Dropper<'a>>> {
+        droppers: I,
+        key: &'b mut ForgettingKey,
+    }
+    impl<'a, I: Iterator<Item = Dropper<'a>>> Drop for DropGuard<'a, '_, I> {
+        fn drop(&mut self) {
+            for dropper in &mut self.droppers {
+                dropper.unlock_drop(self.key);
+            }
+        }
+    }
+    let mut guard = DropGuard { droppers, key };
+    for dropper in &mut guard.droppers {
+        dropper.unlock_drop(guard.key);
+    }
+}
+
+#[derive(Debug)]
+pub struct LocalDroppingKey<'a> {
+    inner: ForgettingKey,
+    droppers: RefCell<Droppers<'a>>,
+}
+
+unsafe impl Key for LocalDroppingKey<'_> {
+    #[inline]
+    fn id(&self) -> KeyId {
+        self.inner.id()
+    }
+}
+
+impl Drop for LocalDroppingKey<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        let droppers = self.droppers.get_mut().take_lifo();
+        unlock_drop_all(droppers, &mut self.inner);
+    }
+}
+
+impl Default for LocalDroppingKey<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> LocalDroppingKey<'a> {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            inner: ForgettingKey::new(),
+            droppers: RefCell::new(Droppers::default()),
+        }
+    }
+
+    #[inline]
+    pub fn lock<T: Locked + 'a>(&self, value: T::Unlocked) -> Dropping<T> {
+        // Reserve space before locking, so that if anything panics, the value
+        // is still unlocked and will be dropped normally.
+        let mut droppers = self.droppers.borrow_mut();
+        droppers.reserve(1);
+        let value: T = self.inner.lock(value);
+        let dropper = Dropper::new(unsafe { value.raw_clone() });
+        let ptr = dropper.ptr;
+        if !droppers.insert(dropper) {
+            unreachable!("box address should be unique");
+        }
+        Dropping { value, ptr }
+    }
+
+    /// Starts tracking a value that is already locked with this key, such as
+    /// the result of [`LockedVec::map()`], so that it is dropped along with
+    /// the key if it is not unlocked first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` was not locked with this key.
+    #[inline]
+    pub fn track<T: Locked + 'a>(&self, value: T) -> Dropping<T> {
+        check_id(self.inner.id(), value.key_id());
+        let mut droppers = self.droppers.borrow_mut();
+        droppers.reserve(1);
+        let dropper = Dropper::new(unsafe { value.raw_clone() });
+        let ptr = dropper.ptr;
+        if !droppers.insert(dropper) {
+            unreachable!("box address should be unique");
+        }
+        Dropping { value, ptr }
+    }
+
+    /// Converts this key into a [`DroppingKey`] with the same [`KeyId`],
+    /// transferring all tracked values to it. Existing [`Dropping`] values
+    /// remain valid for the new key.
+    ///
+    /// # Safety
+    ///
+    /// Every value currently tracked by this key must have a type that
+    /// implements [`Send`] and [`Sync`].
+    #[inline]
+    pub unsafe fn promote(mut self) -> DroppingKey<'a> {
+        let droppers = mem::take(self.droppers.get_mut());
+        DroppingKey {
+            inner: ForgettingKey { id: self.inner.id },
+            droppers: Mutex::new(droppers),
+        }
+    }
+
+    #[inline]
+    pub fn unlock<T: Locked + 'a>(&mut self, value: Dropping<T>) -> T::Unlocked {
+        let ptr = value.ptr;
+        let value = self.inner.unlock(value.value);
+        let Some(dropper) = self.droppers.get_mut().take(&ptr) else {
+            unreachable!("value should correspond to dropper");
+        };
+        dropper.free();
+        value
+    }
+
+    /// Unlocks a vector and shrinks its capacity to fit its length.
+    #[inline]
+    pub fn unlock_shrunk<T: 'a>(&mut self, value: Dropping<LockedVec<T>>) -> Vec<T> {
+        let mut vec = self.unlock(value);
+        vec.shrink_to_fit();
+        vec
+    }
+}
+
+#[derive(Debug)]
+pub struct DroppingKey<'a> {
+    inner: ForgettingKey,
+    droppers: Mutex<Droppers<'a>>,
+}
+
+unsafe impl Send for DroppingKey<'_> {}
+
+unsafe impl Sync for DroppingKey<'_> {}
+
+unsafe impl Key for DroppingKey<'_> {
+    #[inline]
+    fn id(&self) -> KeyId {
+        self.inner.id()
+    }
+}
+
+impl Drop for DroppingKey<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        let droppers = self
+            .droppers
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take_lifo();
+        unlock_drop_all(droppers, &mut self.inner);
+    }
+}
+
+impl Default for DroppingKey<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> DroppingKey<'a> {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            inner: ForgettingKey::new(),
+            droppers: Mutex::new(Droppers::default()),
+        }
+    }
+
+    #[inline]
+    pub fn lock<T: Locked + Send + Sync + 'a>(&self, value: T::Unlocked) -> Dropping<T> {
+        let mut droppers = self.droppers.lock().unwrap_or_else(PoisonError::into_inner);
+        droppers.reserve(1);
+        let value: T = self.inner.lock(value);
+        let dropper = Dropper::new(unsafe { value.raw_clone() });
+        let ptr = dropper.ptr;
+        if !droppers.insert(dropper) {
+            unreachable!("box address should be unique");
+        }
+        Dropping { value, ptr }
+    }
+
+    /// Starts tracking a value that is already locked with this key, such as
+    /// the result of [`LockedVec::map()`], so that it is dropped along with
+    /// the key if it is not unlocked first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` was not locked with this key.
+    #[inline]
+    pub fn track<T: Locked + Send + Sync + 'a>(&self, value: T) -> Dropping<T> {
+        check_id(self.inner.id(), value.key_id());
+        let mut droppers = self.droppers.lock().unwrap_or_else(PoisonError::into_inner);
+        droppers.reserve(1);
+        let dropper = Dropper::new(unsafe { value.raw_clone() });
+        let ptr = dropper.ptr;
+        if !droppers.insert(dropper) {
+            unreachable!("box address should be unique");
+        }
+        Dropping { value, ptr }
+    }
+
+    /// Like [`lock()`](Self::lock), but also includes the value in
+    /// [`snapshot()`](Self::snapshot).
+    #[cfg(feature = "serde")]
+    #[inline]
+    pub fn lock_serializable<T: SerializeLocked + Send + Sync + 'a>(
+        &self,
+        value: T::Unlocked,
+    ) -> Dropping<T> {
+        let mut droppers = self.droppers.lock().unwrap_or_else(PoisonError::into_inner);
+        droppers.reserve(1);
+        let value: T = self.inner.lock(value);
+        let dropper = Dropper::new_serializable(unsafe { value.raw_clone() });
+        let ptr = dropper.ptr;
+        if !droppers.insert(dropper) {
+            unreachable!("box address should be unique");
+        }
+        Dropping { value, ptr }
+    }
+
+    /// Deserializes a value and locks it with
+    /// [`lock_serializable()`](Self::lock_serializable). This can restore
+    /// each element of a [`snapshot()`](Self::snapshot), given its type.
+    #[cfg(feature = "serde")]
+    #[inline]
+    pub fn lock_deserialized<'de, T, D>(&self, deserializer: D) -> Result<Dropping<T>, D::Error>
+    where
+        T: SerializeLocked + Send + Sync + 'a,
+        T::Unlocked: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let value = T::Unlocked::deserialize(deserializer)?;
+        Ok(self.lock_serializable(value))
+    }
+
+    /// Returns a view of all tracked values locked with
+    /// [`lock_serializable()`](Self::lock_serializable), which serializes as
+    /// a sequence of their contents in the order they were locked in.
+    ///
+    /// Serializing the snapshot holds this key's lock while it calls each
+    /// value's [`Serialize`] impl, so a value must not lock or unlock anything
+    /// through this key while it is serialized, or it will deadlock.
+    #[cfg(feature = "serde")]
+    #[inline]
+    pub fn snapshot(&self) -> Snapshot<'_, 'a> {
+        Snapshot { key: self }
+    }
+
+    #[inline]
+    pub fn unlock<T: Locked + Send + Sync + 'a>(&mut self, value: Dropping<T>) -> T::Unlocked {
+        let ptr = value.ptr;
+        let value = self.inner.unlock(value.value);
+        let droppers = self.droppers.get_mut();
+        let Some(dropper) = droppers.unwrap_or_else(PoisonError::into_inner).take(&ptr) else {
+            unreachable!("value should correspond to dropper");
+        };
+        dropper.free();
+        value
+    }
+
+    /// Unlocks a vector and shrinks its capacity to fit its length.
+    #[inline]
+    pub fn unlock_shrunk<T: 'a>(&mut self, value: Dropping<LockedVec<T>>) -> Vec<T>
+    where
+        LockedVec<T>: Send + Sync,
+    {
+        let mut vec = self.unlock(value);
+        vec.shrink_to_fit();
+        vec
+    }
+
+    #[inline]
+    pub fn unlock_many<T: Locked + Send + Sync + 'a>(
+        &mut self,
+        values: Vec<Dropping<T>>,
+    ) -> Vec<T::Unlocked> {
+        let droppers = self
+            .droppers
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        values
+            .into_iter()
+            .map(|value| {
+                let ptr = value.ptr;
+                let value = self.inner.unlock(value.value);
+                let Some(dropper) = droppers.take(&ptr) else {
+                    unreachable!("value should correspond to dropper");
+                };
+                dropper.free();
+                value
+            })
+            .collect()
+    }
+}
+
+/// A view of the serializable values tracked by a [`DroppingKey`], created by
+/// [`DroppingKey::snapshot()`].
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct Snapshot<'k, 'a> {
+    key: &'k DroppingKey<'a>,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Snapshot<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let droppers = self
+            .key
+            .droppers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let serialize_fns: Vec<_> = droppers
+            .iter_fifo()
+            .filter_map(|dropper| Some((dropper.ptr, dropper.serialize?)))
+            .collect();
+        let mut seq = serializer.serialize_seq(Some(serialize_fns.len()))?;
+        for (ptr, serialize) in serialize_fns {
+            let mut result = Ok(());
+            unsafe {
+                serialize(ptr, &self.key.inner, &mut |value| {
+                    result = seq.serialize_element(value);
+                })
+            };
+            result?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Either, LockedArc, LockedBox, LockedEither, LockedString, LockedVec};
+    use alloc::{borrow::ToOwned, sync::Arc, vec};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::panic::{self, AssertUnwindSafe};
+
+    /// Counts how many times it has been dropped.
+    #[derive(Debug)]
+    struct Counted<'a>(&'a AtomicUsize);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[derive(Debug)]
+    struct PanicOnDrop;
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("dropped");
+        }
+    }
+
+    #[test]
+    fn unlock_frees_tracked_copy() {
+        let drops = AtomicUsize::new(0);
+        let mut key = LocalDroppingKey::new();
+        let b = key.lock::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops)));
+        let b = key.unlock(b);
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(b);
+        drop(key);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn unlock_many_frees_tracked_copies() {
+        let drops = AtomicUsize::new(0);
+        let mut key = DroppingKey::new();
+        let values = vec![
+            key.lock::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops))),
+            key.lock::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops))),
+        ];
+        let values = key.unlock_many(values);
+        drop(key);
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(values);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn key_drop_drops_tracked_values() {
+        let drops = AtomicUsize::new(0);
+        let key = DroppingKey::new();
+        let _ = key.lock::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops)));
+        let _ = key.lock::<LockedBox<()>>(Box::new(()));
+        let _ = key.lock::<LockedBox<str>>(Box::from("abc"));
+        let _ = key.lock::<LockedVec<Counted<'_>>>(vec![Counted(&drops), Counted(&drops)]);
+        let _ = key.lock::<LockedVec<()>>(vec![(); 3]);
+        let _ = key.lock::<LockedString>("abc".to_owned());
+        let arc = Arc::new(Counted(&drops));
+        let _ = key.lock::<LockedArc<Counted<'_>>>(arc.clone());
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(key);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+        assert_eq!(Arc::strong_count(&arc), 1);
+        drop(arc);
+        assert_eq!(drops.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn key_drop_continues_after_panic() {
+        let drops = AtomicUsize::new(0);
+        let key = LocalDroppingKey::new();
+        let _ = key.lock::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops)));
+        let _ = key.lock::<LockedBox<PanicOnDrop>>(Box::new(PanicOnDrop));
+        let _ = key.lock::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops)));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| drop(key)));
+        assert!(result.is_err());
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn track_drops_mapped_vec() {
+        let drops = AtomicUsize::new(0);
+        let key = DroppingKey::new();
+        let values = key.lock::<LockedVec<i32>>(vec![1, 2, 3]);
+        let mapped = values.map(&key, |_| Counted(&drops));
+        let mapped = key.track(mapped);
+        assert_eq!(mapped.len(), 3);
+        drop(key);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn track_checks_key() {
+        let key = LocalDroppingKey::new();
+        // An empty vector has no allocation to leak after the panic.
+        let other: LockedVec<i32> = ForgettingKey::new().lock(Vec::new());
+        let other_id = other.key_id();
+        let err = crate::locked::catch_wrong_key(|| key.track(other));
+        assert_eq!(err.expected, other_id);
+        assert_eq!(err.got, key.id());
+    }
+
+    #[test]
+    fn key_drop_after_poison() {
+        let drops = AtomicUsize::new(0);
+        let key = DroppingKey::new();
+        let _ = key.lock::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops)));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _droppers = key.droppers.lock().unwrap();
+            panic!("poison");
+        }));
+        assert!(result.is_err());
+        assert!(key.droppers.is_poisoned());
+        let _ = key.lock::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops)));
+        let _ = key.lock::<LockedVec<Counted<'_>>>(vec![Counted(&drops), Counted(&drops)]);
+        drop(key);
+        assert_eq!(drops.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn locked_arc_across_threads() {
+        let arc = Arc::new(1);
+        let mut key = DroppingKey::new();
+        let locked = key.lock::<LockedArc<i32>>(arc.clone());
+        let clones: Vec<_> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| s.spawn(|| (0..8).map(|_| locked.clone(&key)).collect::<Vec<_>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        // Move the tracked value to another thread and back.
+        let locked = std::thread::spawn(move || locked).join().unwrap();
+        assert_eq!(Arc::strong_count(&arc), 2 + 32);
+        drop(clones);
+        assert_eq!(locked.strong_count(&key), 2);
+        let unlocked = std::thread::scope(|s| s.spawn(|| key.unlock(locked)).join().unwrap());
+        assert_eq!(Arc::strong_count(&arc), 2);
+        drop(unlocked);
+        drop(key);
+        assert_eq!(Arc::strong_count(&arc), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_and_restore() {
+        let key = DroppingKey::new();
+        let _ = key.lock_serializable::<LockedVec<u8>>(vec![1, 2]);
+        let _ = key.lock::<LockedVec<u8>>(vec![3]);
+        let _ = key.lock_serializable::<LockedString>("a".to_owned());
+        let _ = key.lock_serializable::<LockedVec<u8>>(Vec::new());
+        let json = serde_json::to_string(&key.snapshot()).unwrap();
+        assert_eq!(json, r#"[[1,2],"a",[]]"#);
+
+        let restored = DroppingKey::new();
+        let values: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        let [a, b, c] = <[_; 3]>::try_from(values).unwrap();
+        let a = restored.lock_deserialized::<LockedVec<u8>, _>(a).unwrap();
+        let b = restored.lock_deserialized::<LockedString, _>(b).unwrap();
+        let c = restored.lock_deserialized::<LockedVec<u8>, _>(c).unwrap();
+        assert_eq!(a.get(&restored), [1, 2]);
+        assert_eq!(b.get(&restored), "a");
+        assert!(c.is_empty());
+        assert_eq!(serde_json::to_string(&restored.snapshot()).unwrap(), json);
+    }
+
+    #[test]
+    fn atomic_through_shared_key() {
+        let key = DroppingKey::new();
+        let counter = key.lock::<LockedBox<AtomicUsize>>(Box::new(AtomicUsize::new(0)));
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        counter.get_atomic(&key).fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.get(&key).load(Ordering::Relaxed), 400);
+    }
+
+    #[test]
+    fn promote_keeps_tracked_values() {
+        let drops = AtomicUsize::new(0);
+        let key = LocalDroppingKey::new();
+        let a = key.lock::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops)));
+        let _ = key.lock::<LockedVec<Counted<'_>>>(vec![Counted(&drops), Counted(&drops)]);
+        let id = key.id();
+        // `Counted` is `Send` and `Sync`.
+        let mut key = unsafe { key.promote() };
+        assert_eq!(key.id(), id);
+        assert_eq!(a.key_id(), id);
+        let a = key.unlock(a);
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(key);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+        drop(a);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+
+    /// Records its id in a shared log when dropped.
+    struct Logged<'a> {
+        id: usize,
+        log: &'a Mutex<Vec<usize>>,
+    }
+
+    impl Drop for Logged<'_> {
+        fn drop(&mut self) {
+            self.log.lock().unwrap().push(self.id);
+        }
+    }
+
+    #[test]
+    fn local_key_drops_in_reverse_order() {
+        let log = Mutex::new(Vec::new());
+        let key = LocalDroppingKey::new();
+        for id in 0..4 {
+            let _ = key.lock::<LockedBox<Logged<'_>>>(Box::new(Logged { id, log: &log }));
+        }
+        drop(key);
+        assert_eq!(*log.lock().unwrap(), [3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn key_drops_in_reverse_order() {
+        let log = Mutex::new(Vec::new());
+        let mut key = DroppingKey::new();
+        let mut values: Vec<_> = (0..5)
+            .map(|id| key.lock::<LockedBox<Logged<'_>>>(Box::new(Logged { id, log: &log })))
+            .collect();
+        // Unlocking a value in the middle keeps the order of the rest.
+        drop(key.unlock(values.remove(2)));
+        drop(values);
+        drop(key);
+        assert_eq!(*log.lock().unwrap(), [2, 4, 3, 1, 0]);
+    }
+
+    #[test]
+    fn key_drop_drops_either() {
+        let drops = AtomicUsize::new(0);
+        let key = DroppingKey::new();
+        let left = key.lock::<LockedEither<LockedBox<Counted<'_>>, Counted<'_>>>(Either::Left(
+            Box::new(Counted(&drops)),
+        ));
+        let _ = key.lock::<LockedEither<LockedBox<Counted<'_>>, Counted<'_>>>(Either::Right(
+            Counted(&drops),
+        ));
+        assert!(left.left().unwrap().try_get(&key).is_ok());
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(key);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn unlock_shrunk() {
+        let mut local_key = LocalDroppingKey::new();
+        let mut key = DroppingKey::new();
+        let with_spare_capacity = || {
+            let mut vec = Vec::with_capacity(16);
+            vec.extend([1, 2, 3]);
+            vec
+        };
+        let v = local_key.lock::<LockedVec<i32>>(with_spare_capacity());
+        let w = key.lock::<LockedVec<i32>>(with_spare_capacity());
+        for vec in [local_key.unlock_shrunk(v), key.unlock_shrunk(w)] {
+            assert_eq!(vec, [1, 2, 3]);
+            assert_eq!(vec.capacity(), vec.len());
+        }
+    }
+}
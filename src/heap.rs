@@ -0,0 +1,3918 @@
+use crate::{
+    locked::{check_id, try_check_id},
+    AccessLocked, Key, KeyId, Locked, LockedMut, LockedRef, WrongKeyError,
+};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    ffi::CString,
+    rc::{self, Rc},
+    string::String,
+    sync::{self, Arc},
+    vec::Vec,
+};
+use core::{
+    borrow::Borrow,
+    cell::UnsafeCell,
+    ffi::{CStr, FromBytesWithNulError},
+    fmt::{self, Debug, Display, Formatter},
+    iter::{FusedIterator, Rev, Sum},
+    marker::PhantomData,
+    mem::{self, ManuallyDrop, MaybeUninit},
+    ops::{Bound, Deref, DerefMut, Range, RangeBounds},
+    pin::Pin,
+    ptr::{self, NonNull},
+    slice,
+    str::{self, Utf8Error},
+};
+
+#[cfg(feature = "serde")]
+use crate::SerializeLocked;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+#[cfg(feature = "std")]
+use core::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::{
+    collections::HashMap,
+    hash::RandomState,
+    sync::{Mutex, MutexGuard},
+};
+
+#[cfg(feature = "unicode-segmentation")]
+use unicode_segmentation::UnicodeSegmentation;
+
+#[cfg(feature = "bytemuck")]
+use bytemuck::Pod;
+
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+/// A locked [`Box`].
+///
+/// This is [`Send`] when `T` is [`Send`], and [`Sync`] when `T` is both
+/// [`Send`] and [`Sync`], since [`get_mut()`](Self::get_mut) only needs a
+/// shared reference to the box.
+#[derive(Debug)]
+pub struct LockedBox<T: ?Sized> {
+    ptr: NonNull<T>,
+    key_id: KeyId,
+}
+
+unsafe impl<T: ?Sized + Send> Send for LockedBox<T> {}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for LockedBox<T> {}
+
+impl<T: ?Sized> Locked for LockedBox<T> {
+    type Unlocked = Box<T>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(b: Self::Unlocked, key: &K) -> Self {
+        let key_id = key.id();
+        let ptr = NonNull::new(Box::into_raw(b)).unwrap();
+        Self { ptr, key_id }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        check_id(key.id(), self.key_id);
+        unsafe { Box::from_raw(self.ptr.as_ptr()) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+impl<T: ?Sized> LockedBox<T> {
+    /// Returns a shared reference to the contents. If they have interior
+    /// mutability, e.g., if they are atomics, then they can be modified
+    /// through this reference with only a shared key.
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T {
+        check_id(key.id(), self.key_id);
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Same as [`get()`](Self::get), but only for contents that can be shared
+    /// between threads, such as atomics.
+    ///
+    /// With a [`Sync`] key such as `DroppingKey`, several threads can each
+    /// call this with a shared reference to the key and modify the contents
+    /// at the same time.
+    #[inline]
+    pub fn get_atomic<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T
+    where
+        T: Sync,
+    {
+        self.get(key)
+    }
+
+    #[inline]
+    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut T {
+        check_id(key.id(), self.key_id);
+        unsafe { { self.ptr }.as_mut() }
+    }
+
+    #[inline]
+    pub fn try_get<'k, K: ?Sized + Key>(&self, key: &'k K) -> Result<&'k T, WrongKeyError> {
+        try_check_id(key.id(), self.key_id)?;
+        unsafe { Ok(self.ptr.as_ref()) }
+    }
+
+    #[inline]
+    pub fn try_get_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+    ) -> Result<&'k mut T, WrongKeyError> {
+        try_check_id(key.id(), self.key_id)?;
+        unsafe { Ok({ self.ptr }.as_mut()) }
+    }
+
+    /// # Safety
+    ///
+    /// `key.id()` must be equal to `self.key_id()`. Otherwise, the returned
+    /// reference may alias a reference obtained through the correct key,
+    /// which is undefined behavior.
+    #[inline]
+    pub unsafe fn get_unchecked<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T {
+        let _ = key;
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// # Safety
+    ///
+    /// `key.id()` must be equal to `self.key_id()`. Otherwise, the returned
+    /// reference may alias a reference obtained through the correct key,
+    /// which is undefined behavior.
+    #[inline]
+    pub unsafe fn get_unchecked_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut T {
+        let _ = key;
+        unsafe { { self.ptr }.as_mut() }
+    }
+
+    #[inline]
+    pub fn leak<'k, K: ?Sized + Key>(self, key: &'k mut K) -> &'k mut T
+    where
+        T: 'k,
+    {
+        check_id(key.id(), self.key_id);
+        unsafe { &mut *self.ptr.as_ptr() }
+    }
+
+    /// Consumes the box, returning a [`LockedMut`] to its contents with the
+    /// same [`KeyId`]. As with [`Box::leak()`], the allocation is never freed.
+    #[inline]
+    pub fn leak_locked<'a>(self) -> LockedMut<'a, T>
+    where
+        T: 'a,
+    {
+        LockedMut {
+            ptr: self.ptr,
+            key_id: self.key_id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a [`LockedMut`] referring to the contents of the box, with the
+    /// same [`KeyId`].
+    #[inline]
+    pub fn as_locked_mut(&mut self) -> LockedMut<'_, T> {
+        LockedMut {
+            ptr: self.ptr,
+            key_id: self.key_id,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a [`LockedRef`] referring to the contents of the box, with the
+    /// same [`KeyId`].
+    ///
+    /// This borrows the box mutably, since the [`LockedRef`] can be unlocked
+    /// into a `&T` that outlives the key borrow, while
+    /// [`get_mut()`](Self::get_mut) only needs a shared reference to the box.
+    #[inline]
+    pub fn as_locked_ref(&mut self) -> LockedRef<'_, T> {
+        LockedRef {
+            ptr: self.ptr,
+            key_id: self.key_id,
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn into_raw_parts(self) -> (NonNull<T>, KeyId) {
+        (self.ptr, self.key_id)
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` and `key_id` must have been obtained from a previous call to
+    /// [`LockedBox::into_raw_parts()`].
+    #[inline]
+    pub unsafe fn from_raw_parts(ptr: NonNull<T>, key_id: KeyId) -> Self {
+        Self { ptr, key_id }
+    }
+}
+
+impl<T> LockedBox<T> {
+    /// Applies `f` to the contents of the box, returning the result in a new
+    /// box with the same [`KeyId`].
+    #[inline]
+    pub fn map<K: ?Sized + Key, U, F: FnOnce(T) -> U>(self, key: &mut K, f: F) -> LockedBox<U> {
+        let key_id = self.key_id;
+        let value = *unsafe { self.raw_unlock(key) };
+        let ptr = NonNull::new(Box::into_raw(Box::new(f(value)))).unwrap();
+        LockedBox { ptr, key_id }
+    }
+}
+
+impl<T> LockedBox<[T]> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ptr.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ptr.len() == 0
+    }
+}
+
+impl<T> LockedBox<[MaybeUninit<T>]> {
+    /// Converts the box into a [`LockedBox<[T]>`](LockedBox) with the same
+    /// allocation and [`KeyId`].
+    ///
+    /// # Safety
+    ///
+    /// Every element of the slice must be initialized.
+    #[inline]
+    pub unsafe fn assume_init<K: ?Sized + Key>(self, key: &mut K) -> LockedBox<[T]> {
+        check_id(key.id(), self.key_id);
+        let ptr = unsafe { NonNull::new_unchecked(self.ptr.as_ptr() as *mut [T]) };
+        LockedBox {
+            ptr,
+            key_id: self.key_id,
+        }
+    }
+}
+
+impl<T: ?Sized> LockedBox<UnsafeCell<T>> {
+    /// Returns a raw pointer to the contents of the cell.
+    ///
+    /// The pointer remains valid until the box is unlocked. Accesses through
+    /// it are subject to the usual rules of [`UnsafeCell::get()`], and must
+    /// not conflict with any reference obtained from [`get()`](Self::get) or
+    /// [`get_mut()`](Self::get_mut).
+    #[inline]
+    pub fn get_cell<K: ?Sized + Key>(&self, key: &K) -> *mut T {
+        self.get(key).get()
+    }
+}
+
+/// A locked [`Vec`].
+///
+/// Like [`LockedBox`], this is [`Send`] when `T` is [`Send`], and [`Sync`]
+/// when `T` is both [`Send`] and [`Sync`].
+#[derive(Debug)]
+pub struct LockedVec<T> {
+    ptr: NonNull<T>,
+    len: usize,
+    capacity: usize,
+    key_id: KeyId,
+}
+
+unsafe impl<T: Send> Send for LockedVec<T> {}
+
+unsafe impl<T: Send + Sync> Sync for LockedVec<T> {}
+
+struct VecMut<'a, T> {
+    vec: ManuallyDrop<Vec<T>>,
+    locked: &'a mut LockedVec<T>,
+}
+
+impl<T> Deref for VecMut<'_, T> {
+    type Target = Vec<T>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.vec
+    }
+}
+
+impl<T> DerefMut for VecMut<'_, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.vec
+    }
+}
+
+impl<T> Drop for VecMut<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.locked.ptr = NonNull::new(self.vec.as_mut_ptr()).unwrap();
+        self.locked.len = self.vec.len();
+        self.locked.capacity = self.vec.capacity();
+    }
+}
+
+impl<T> Locked for LockedVec<T> {
+    type Unlocked = Vec<T>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(vec: Self::Unlocked, key: &K) -> Self {
+        Self::from_vec(vec, key.id())
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        check_id(key.id(), self.key_id);
+        unsafe { Vec::from_raw_parts(self.ptr.as_ptr(), self.len, self.capacity) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+impl<T> LockedVec<T> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [T] {
+        check_id(key.id(), self.key_id);
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    #[inline]
+    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut [T] {
+        check_id(key.id(), self.key_id);
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    #[inline]
+    pub fn try_get<'k, K: ?Sized + Key>(&self, key: &'k K) -> Result<&'k [T], WrongKeyError> {
+        try_check_id(key.id(), self.key_id)?;
+        unsafe { Ok(slice::from_raw_parts(self.ptr.as_ptr(), self.len)) }
+    }
+
+    #[inline]
+    pub fn try_get_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+    ) -> Result<&'k mut [T], WrongKeyError> {
+        try_check_id(key.id(), self.key_id)?;
+        unsafe { Ok(slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len)) }
+    }
+
+    /// # Safety
+    ///
+    /// `key.id()` must be equal to `self.key_id()`. Otherwise, the returned
+    /// reference may alias a reference obtained through the correct key,
+    /// which is undefined behavior.
+    #[inline]
+    pub unsafe fn get_unchecked<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [T] {
+        let _ = key;
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// # Safety
+    ///
+    /// `key.id()` must be equal to `self.key_id()`. Otherwise, the returned
+    /// reference may alias a reference obtained through the correct key,
+    /// which is undefined behavior.
+    #[inline]
+    pub unsafe fn get_unchecked_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut [T] {
+        let _ = key;
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    #[inline]
+    pub fn get_buf<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [MaybeUninit<T>] {
+        check_id(key.id(), self.key_id);
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr().cast(), self.capacity) }
+    }
+
+    #[inline]
+    pub fn get_buf_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut [MaybeUninit<T>] {
+        check_id(key.id(), self.key_id);
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr().cast(), self.capacity) }
+    }
+
+    /// Returns the pointers to the first element and one past the last
+    /// element, as in [`slice::as_ptr_range()`].
+    #[inline]
+    pub fn as_ptr_range<K: ?Sized + Key>(&self, key: &K) -> Range<*const T> {
+        self.get(key).as_ptr_range()
+    }
+
+    /// Returns the pointers to the first element and one past the last
+    /// element, as in [`slice::as_mut_ptr_range()`].
+    #[inline]
+    pub fn as_mut_ptr_range<K: ?Sized + Key>(&self, key: &mut K) -> Range<*mut T> {
+        self.get_mut(key).as_mut_ptr_range()
+    }
+
+    /// Calls `f` on each overlapping window of length `N`, in order.
+    ///
+    /// Like [`get_mut()`](Self::get_mut), this only needs `&self`, since the
+    /// mutable borrow of the key already rules out any other access to the
+    /// elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero.
+    #[inline]
+    pub fn for_each_window_mut<K: ?Sized + Key, const N: usize, F: FnMut(&mut [T; N])>(
+        &self,
+        key: &mut K,
+        mut f: F,
+    ) {
+        assert!(N != 0, "window size must be non-zero");
+        let slice = self.get_mut(key);
+        for start in 0..slice.len().saturating_sub(N - 1) {
+            f((&mut slice[start..start + N]).try_into().unwrap());
+        }
+    }
+
+    #[inline]
+    pub fn sort_by_cached_key<K: ?Sized + Key, U: Ord, F: FnMut(&T) -> U>(
+        &self,
+        key: &mut K,
+        f: F,
+    ) {
+        self.get_mut(key).sort_by_cached_key(f);
+    }
+
+    #[inline]
+    pub fn split_first<'k, K: ?Sized + Key>(&self, key: &'k K) -> Option<(&'k T, &'k [T])> {
+        self.get(key).split_first()
+    }
+
+    #[inline]
+    pub fn split_first_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+    ) -> Option<(&'k mut T, &'k mut [T])> {
+        self.get_mut(key).split_first_mut()
+    }
+
+    #[inline]
+    pub fn split_last<'k, K: ?Sized + Key>(&self, key: &'k K) -> Option<(&'k T, &'k [T])> {
+        self.get(key).split_last()
+    }
+
+    #[inline]
+    pub fn split_last_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+    ) -> Option<(&'k mut T, &'k mut [T])> {
+        self.get_mut(key).split_last_mut()
+    }
+
+    #[inline]
+    pub fn iter_rev<'k, K: ?Sized + Key>(&self, key: &'k K) -> Rev<slice::Iter<'k, T>> {
+        self.get(key).iter().rev()
+    }
+
+    /// Returns the last `n` elements, or all elements if there are fewer than
+    /// `n`.
+    #[inline]
+    pub fn last_n<'k, K: ?Sized + Key>(&self, key: &'k K, n: usize) -> &'k [T] {
+        let slice = self.get(key);
+        &slice[slice.len().saturating_sub(n)..]
+    }
+
+    #[inline]
+    fn from_vec(vec: Vec<T>, key_id: KeyId) -> Self {
+        let (len, capacity) = (vec.len(), vec.capacity());
+        let ptr = ManuallyDrop::new(vec).as_mut_ptr();
+        Self {
+            ptr: NonNull::new(ptr).unwrap(),
+            len,
+            capacity,
+            key_id,
+        }
+    }
+
+    #[inline]
+    fn vec_mut<K: ?Sized + Key>(&mut self, key: &mut K) -> VecMut<'_, T> {
+        check_id(key.id(), self.key_id);
+        let vec = unsafe { Vec::from_raw_parts(self.ptr.as_ptr(), self.len, self.capacity) };
+        VecMut {
+            vec: ManuallyDrop::new(vec),
+            locked: self,
+        }
+    }
+
+    /// Removes the given range from the vector, returning an iterator over the
+    /// removed elements. The key remains borrowed until the iterator is
+    /// dropped.
+    ///
+    /// If the iterator is leaked, the vector may lose more elements than the
+    /// range, as with [`Vec::drain()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than the end, or if the end
+    /// is greater than the length of the vector.
+    #[inline]
+    pub fn drain<'k, K: ?Sized + Key, R: RangeBounds<usize>>(
+        &'k mut self,
+        key: &'k mut K,
+        range: R,
+    ) -> LockedDrain<'k, T> {
+        check_id(key.id(), self.key_id);
+        let len = self.len;
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start
+                .checked_add(1)
+                .expect("range start should not overflow"),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.checked_add(1).expect("range end should not overflow"),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        };
+        if start > end {
+            panic!("slice index starts at {start} but ends at {end}");
+        }
+        if end > len {
+            panic!("range end index {end} out of range for slice of length {len}");
+        }
+        self.len = start;
+        LockedDrain {
+            front: start,
+            back: end,
+            tail_start: end,
+            tail_len: len - end,
+            locked: self,
+            _key: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn drain_to_vec<K: ?Sized + Key, R: RangeBounds<usize>>(
+        &mut self,
+        key: &mut K,
+        range: R,
+    ) -> Vec<T> {
+        self.vec_mut(key).drain(range).collect()
+    }
+
+    #[inline]
+    pub fn extend_from_locked<K1: ?Sized + Key, K2: ?Sized + Key>(
+        &mut self,
+        my_key: &mut K1,
+        other: &LockedVec<T>,
+        other_key: &K2,
+    ) where
+        T: Clone,
+    {
+        let other = other.get(other_key);
+        self.vec_mut(my_key).extend_from_slice(other);
+    }
+
+    /// Retains only the elements for which `f` returns `true`, evaluating `f`
+    /// on all elements in parallel before removing any of them.
+    #[cfg(feature = "rayon")]
+    #[inline]
+    pub fn par_retain<K: ?Sized + Key, F: Fn(&T) -> bool + Send + Sync>(
+        &mut self,
+        key: &mut K,
+        f: F,
+    ) where
+        T: Sync,
+    {
+        let mut vec = self.vec_mut(key);
+        let keep: Vec<bool> = vec.par_iter().map(f).collect();
+        let mut keep = keep.into_iter();
+        vec.retain(|_| keep.next().unwrap());
+    }
+
+    #[inline]
+    pub fn with_slice<K: ?Sized + Key, R>(&self, key: &K, f: impl FnOnce(&[T]) -> R) -> R {
+        f(self.get(key))
+    }
+
+    #[inline]
+    pub fn with_slice_mut<K: ?Sized + Key, R>(
+        &self,
+        key: &mut K,
+        f: impl FnOnce(&mut [T]) -> R,
+    ) -> R {
+        f(self.get_mut(key))
+    }
+
+    #[inline]
+    pub fn chunks_exact<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k K,
+        chunk_size: usize,
+    ) -> slice::ChunksExact<'k, T> {
+        self.get(key).chunks_exact(chunk_size)
+    }
+
+    #[inline]
+    pub fn chunks_exact_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+        chunk_size: usize,
+    ) -> slice::ChunksExactMut<'k, T> {
+        self.get_mut(key).chunks_exact_mut(chunk_size)
+    }
+
+    #[inline]
+    pub fn rchunks<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k K,
+        chunk_size: usize,
+    ) -> slice::RChunks<'k, T> {
+        self.get(key).rchunks(chunk_size)
+    }
+
+    #[inline]
+    pub fn rchunks_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+        chunk_size: usize,
+    ) -> slice::RChunksMut<'k, T> {
+        self.get_mut(key).rchunks_mut(chunk_size)
+    }
+
+    #[inline]
+    pub fn chunk_by<'k, K: ?Sized + Key, F: FnMut(&T, &T) -> bool>(
+        &self,
+        key: &'k K,
+        pred: F,
+    ) -> slice::ChunkBy<'k, T, F> {
+        self.get(key).chunk_by(pred)
+    }
+
+    /// Reinterprets the elements of this vector as elements of type `U`,
+    /// reusing the same allocation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` and `U` do not have the same size and alignment.
+    ///
+    /// # Safety
+    ///
+    /// Every element of type `T` in the vector must also be a valid value of
+    /// type `U`, e.g., if `T` is a `#[repr(transparent)]` wrapper around `U`.
+    #[inline]
+    pub unsafe fn reinterpret<U>(self) -> LockedVec<U> {
+        assert_eq!(mem::size_of::<T>(), mem::size_of::<U>(), "size mismatch");
+        assert_eq!(
+            mem::align_of::<T>(),
+            mem::align_of::<U>(),
+            "alignment mismatch"
+        );
+        LockedVec {
+            ptr: self.ptr.cast(),
+            len: self.len,
+            capacity: self.capacity,
+            key_id: self.key_id,
+        }
+    }
+
+    /// Splits the vector at `at`, returning the elements after it in a new
+    /// vector with the same [`KeyId`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    #[inline]
+    pub fn split_off<K: ?Sized + Key>(&mut self, key: &mut K, at: usize) -> Self {
+        let tail = self.vec_mut(key).split_off(at);
+        Self::from_vec(tail, self.key_id)
+    }
+
+    /// Moves all elements of `other` onto the end of this vector, leaving
+    /// `other` empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` does not match both vectors.
+    #[inline]
+    pub fn append<K: ?Sized + Key>(&mut self, key: &mut K, other: &mut Self) {
+        let mut other = other.vec_mut(key);
+        self.vec_mut(key).append(&mut other);
+    }
+
+    /// Retains only the elements for which `f` returns `true`. `f` only
+    /// receives `&T`, so it is not given the key.
+    #[inline]
+    pub fn retain<K: ?Sized + Key, F: FnMut(&T) -> bool>(&mut self, key: &mut K, f: F) {
+        self.vec_mut(key).retain(f);
+    }
+
+    /// Retains the elements for which `f` returns `true`, and moves the rest
+    /// onto the end of `sink`, in order.
+    #[inline]
+    pub fn retain_collect<K: ?Sized + Key, F: FnMut(&T) -> bool>(
+        &mut self,
+        key: &mut K,
+        mut f: F,
+        sink: &mut Vec<T>,
+    ) {
+        sink.extend(self.vec_mut(key).extract_if(.., |x| !f(x)));
+    }
+
+    /// Retains the longest prefix of elements for which `f` returns `true`,
+    /// and drops the rest.
+    #[inline]
+    pub fn retain_while<K: ?Sized + Key, F: FnMut(&T) -> bool>(&mut self, key: &mut K, mut f: F) {
+        let mut vec = self.vec_mut(key);
+        let len = vec.iter().position(|x| !f(x)).unwrap_or(vec.len());
+        vec.truncate(len);
+    }
+
+    /// Drops the longest prefix of elements for which `f` returns `true`, and
+    /// retains the rest.
+    #[inline]
+    pub fn drop_while<K: ?Sized + Key, F: FnMut(&T) -> bool>(&mut self, key: &mut K, mut f: F) {
+        let mut vec = self.vec_mut(key);
+        let len = vec.iter().position(|x| !f(x)).unwrap_or(vec.len());
+        vec.drain(..len);
+    }
+
+    /// Inserts an element at the front of the vector, shifting all other
+    /// elements. This takes O(n) time.
+    #[inline]
+    pub fn push_front<K: ?Sized + Key>(&mut self, key: &mut K, value: T) {
+        self.vec_mut(key).insert(0, value);
+    }
+
+    /// Removes the first element of the vector, shifting all other elements,
+    /// or returns `None` if it is empty. This takes O(n) time.
+    #[inline]
+    pub fn pop_front<K: ?Sized + Key>(&mut self, key: &mut K) -> Option<T> {
+        let mut vec = self.vec_mut(key);
+        if vec.is_empty() {
+            None
+        } else {
+            Some(vec.remove(0))
+        }
+    }
+
+    #[inline]
+    pub fn push<K: ?Sized + Key>(&mut self, key: &mut K, value: T) {
+        self.vec_mut(key).push(value);
+    }
+
+    #[inline]
+    pub fn reserve<K: ?Sized + Key>(&mut self, key: &mut K, additional: usize) {
+        self.vec_mut(key).reserve(additional);
+    }
+
+    #[inline]
+    pub fn reserve_exact<K: ?Sized + Key>(&mut self, key: &mut K, additional: usize) {
+        self.vec_mut(key).reserve_exact(additional);
+    }
+
+    /// Reserves space for at least `additional` more elements, and returns
+    /// the first `additional` slots after the end of the vector. These can be
+    /// initialized and then included with [`set_len()`](Self::set_len).
+    ///
+    /// The slice is only valid until the next mutation of the vector, since
+    /// that may reallocate it. It borrows the vector mutably to enforce this,
+    /// so the slots must be written before calling `set_len()`.
+    #[inline]
+    pub fn grow_uninit<K: ?Sized + Key>(
+        &mut self,
+        key: &mut K,
+        additional: usize,
+    ) -> &mut [MaybeUninit<T>] {
+        self.vec_mut(key).reserve(additional);
+        let ptr = unsafe { self.ptr.add(self.len) };
+        unsafe { slice::from_raw_parts_mut(ptr.as_ptr().cast(), additional) }
+    }
+
+    /// # Safety
+    ///
+    /// `new_len` must be less than or equal to [`capacity()`](Self::capacity),
+    /// and the elements at `old_len..new_len` must be initialized.
+    #[inline]
+    pub unsafe fn set_len<K: ?Sized + Key>(&mut self, key: &mut K, new_len: usize) {
+        check_id(key.id(), self.key_id);
+        self.len = new_len;
+    }
+
+    /// Shrinks the capacity of the vector to fit its length, if the length is
+    /// less than `ratio` times the capacity.
+    #[inline]
+    pub fn maybe_shrink<K: ?Sized + Key>(&mut self, key: &mut K, ratio: f32) {
+        let mut vec = self.vec_mut(key);
+        if (vec.len() as f32) < ratio * vec.capacity() as f32 {
+            vec.shrink_to_fit();
+        }
+    }
+
+    #[inline]
+    pub fn truncate<K: ?Sized + Key>(&mut self, key: &mut K, len: usize) {
+        self.vec_mut(key).truncate(len);
+    }
+
+    /// Removes all elements from the vector, keeping its capacity.
+    #[inline]
+    pub fn clear<K: ?Sized + Key>(&mut self, key: &mut K) {
+        self.vec_mut(key).clear();
+    }
+
+    #[inline]
+    pub fn resize<K: ?Sized + Key>(&mut self, key: &mut K, new_len: usize, value: T)
+    where
+        T: Clone,
+    {
+        self.vec_mut(key).resize(new_len, value);
+    }
+
+    /// Removes the last element of the vector, or returns `None` if it is
+    /// empty. This takes O(1) time.
+    #[inline]
+    pub fn pop<K: ?Sized + Key>(&mut self, key: &mut K) -> Option<T> {
+        self.vec_mut(key).pop()
+    }
+
+    /// Inserts an element at `index`, shifting all elements after it. This
+    /// takes O(n) time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    #[inline]
+    pub fn insert<K: ?Sized + Key>(&mut self, key: &mut K, index: usize, value: T) {
+        self.vec_mut(key).insert(index, value);
+    }
+
+    /// Removes the element at `index`, shifting all elements after it. This
+    /// takes O(n) time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn remove<K: ?Sized + Key>(&mut self, key: &mut K, index: usize) -> T {
+        self.vec_mut(key).remove(index)
+    }
+
+    /// Removes the element at `index`, replacing it with the last element.
+    /// This takes O(1) time, but does not preserve the order of the elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn swap_remove<K: ?Sized + Key>(&mut self, key: &mut K, index: usize) -> T {
+        self.vec_mut(key).swap_remove(index)
+    }
+
+    /// Reorders the elements so that all elements for which `pred` returns
+    /// `true` precede all elements for which it returns `false`, and returns
+    /// the number of the former. The order within each group is unspecified.
+    #[inline]
+    pub fn partition<K: ?Sized + Key, F: FnMut(&T) -> bool>(
+        &mut self,
+        key: &mut K,
+        mut pred: F,
+    ) -> usize {
+        let slice = self.get_mut(key);
+        let mut split = 0;
+        for i in 0..slice.len() {
+            if pred(&slice[i]) {
+                slice.swap(split, i);
+                split += 1;
+            }
+        }
+        split
+    }
+
+    /// Sorts the vector and removes all duplicate elements, so that it
+    /// contains each distinct element once, in ascending order.
+    #[inline]
+    pub fn sort_dedup<K: ?Sized + Key>(&mut self, key: &mut K)
+    where
+        T: Ord,
+    {
+        let mut vec = self.vec_mut(key);
+        vec.sort();
+        vec.dedup();
+    }
+
+    /// Removes consecutive repeated elements, returning the removed elements
+    /// in their original order.
+    #[inline]
+    pub fn dedup_drain<K: ?Sized + Key>(&mut self, key: &mut K) -> Vec<T>
+    where
+        T: PartialEq,
+    {
+        struct FillGap<'a, T> {
+            vec: &'a mut Vec<T>,
+            read: usize,
+            write: usize,
+            len: usize,
+        }
+        impl<T> Drop for FillGap<'_, T> {
+            fn drop(&mut self) {
+                unsafe {
+                    let ptr = self.vec.as_mut_ptr();
+                    let tail = self.len - self.read;
+                    ptr::copy(ptr.add(self.read), ptr.add(self.write), tail);
+                    self.vec.set_len(self.write + tail);
+                }
+            }
+        }
+        let mut vec = self.vec_mut(key);
+        let mut removed = Vec::new();
+        let len = vec.len();
+        if len <= 1 {
+            return removed;
+        }
+        unsafe { vec.set_len(0) };
+        let mut gap = FillGap {
+            vec: &mut vec,
+            read: 1,
+            write: 1,
+            len,
+        };
+        let ptr = gap.vec.as_mut_ptr();
+        while gap.read < len {
+            let read = unsafe { ptr.add(gap.read) };
+            if unsafe { *read == *ptr.add(gap.write - 1) } {
+                let value = unsafe { ptr::read(read) };
+                gap.read += 1;
+                removed.push(value);
+            } else {
+                unsafe { ptr::copy(read, ptr.add(gap.write), 1) };
+                gap.read += 1;
+                gap.write += 1;
+            }
+        }
+        removed
+    }
+
+    /// Returns the element at `index`, first pushing `f()` if `index` is equal
+    /// to the length of the vector.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length of the vector.
+    #[inline]
+    pub fn get_or_insert_with<'k, K: ?Sized + Key, F: FnOnce() -> T>(
+        &mut self,
+        key: &'k mut K,
+        index: usize,
+        f: F,
+    ) -> &'k mut T {
+        let mut vec = self.vec_mut(key);
+        let len = vec.len();
+        if index == len {
+            vec.push(f());
+        } else if index > len {
+            panic!("insertion index (is {index}) should be <= len (is {len})");
+        }
+        drop(vec);
+        &mut self.get_mut(key)[index]
+    }
+
+    /// Shortens the vector to `len` elements, moving the removed elements onto
+    /// the end of `sink` in order. Does nothing if `len` is greater than or
+    /// equal to the current length.
+    #[inline]
+    pub fn truncate_into<K: ?Sized + Key>(&mut self, key: &mut K, len: usize, sink: &mut Vec<T>) {
+        let mut vec = self.vec_mut(key);
+        if len < vec.len() {
+            sink.extend(vec.drain(len..));
+        }
+    }
+
+    #[inline]
+    pub fn max<'k, K: ?Sized + Key>(&self, key: &'k K) -> Option<&'k T>
+    where
+        T: Ord,
+    {
+        self.get(key).iter().max()
+    }
+
+    #[inline]
+    pub fn min<'k, K: ?Sized + Key>(&self, key: &'k K) -> Option<&'k T>
+    where
+        T: Ord,
+    {
+        self.get(key).iter().min()
+    }
+
+    #[inline]
+    pub fn sum<'k, K: ?Sized + Key, S: Sum<&'k T>>(&self, key: &'k K) -> S
+    where
+        T: 'k,
+    {
+        self.get(key).iter().sum()
+    }
+
+    /// Returns whether the vector contains `x`, using a binary search. The
+    /// vector must be sorted; otherwise, the result is unspecified.
+    #[inline]
+    pub fn contains_sorted<K: ?Sized + Key>(&self, key: &K, x: &T) -> bool
+    where
+        T: Ord,
+    {
+        self.get(key).binary_search(x).is_ok()
+    }
+
+    /// Returns each run of consecutive equal elements as the element and the
+    /// length of the run, in order.
+    #[inline]
+    pub fn run_lengths<K: ?Sized + Key>(&self, key: &K) -> Vec<(T, usize)>
+    where
+        T: Clone + PartialEq,
+    {
+        self.get(key)
+            .chunk_by(|a, b| a == b)
+            .map(|run| (run[0].clone(), run.len()))
+            .collect()
+    }
+
+    /// Returns a new vector with the same [`KeyId`], containing the result of
+    /// applying `f` to each element.
+    ///
+    /// The new vector is not tracked by any `DroppingKey` that tracks this
+    /// one. Pass it to `DroppingKey::track()` to have it dropped along with
+    /// the key; otherwise it is leaked.
+    #[inline]
+    pub fn map<K: ?Sized + Key, U, F: FnMut(&T) -> U>(&self, key: &K, f: F) -> LockedVec<U> {
+        let vec = self.get(key).iter().map(f).collect();
+        LockedVec::from_vec(vec, self.key_id)
+    }
+
+    /// Replaces the contents of the vector with a clone of `src`, reusing the
+    /// existing allocation if it is large enough.
+    #[inline]
+    pub fn clone_from_locked<K: ?Sized + Key>(&mut self, key: &mut K, src: &[T])
+    where
+        T: Clone,
+    {
+        let mut vec = self.vec_mut(key);
+        vec.truncate(src.len());
+        let (init, tail) = src.split_at(vec.len());
+        vec.clone_from_slice(init);
+        vec.extend_from_slice(tail);
+    }
+
+    /// Appends elements from `iter` into the spare capacity of the vector,
+    /// without reallocating. Stops when the vector is full or `iter` is
+    /// exhausted, and returns the number of elements appended.
+    #[inline]
+    pub fn fill_from_iter<K: ?Sized + Key, I: Iterator<Item = T>>(
+        &mut self,
+        key: &mut K,
+        iter: I,
+    ) -> usize {
+        let mut vec = self.vec_mut(key);
+        let old_len = vec.len();
+        for value in iter.take(vec.capacity() - old_len) {
+            vec.push(value);
+        }
+        vec.len() - old_len
+    }
+
+    /// Converts the vector into a [`LockedArc<[T]>`](LockedArc) with the same
+    /// [`KeyId`]. This moves the elements into a new allocation.
+    #[inline]
+    pub fn into_arc_slice<K: ?Sized + Key>(self, key: &mut K) -> LockedArc<[T]> {
+        let key_id = self.key_id;
+        let arc: Arc<[T]> = Arc::from(unsafe { self.raw_unlock(key) });
+        let ptr = NonNull::new(Arc::into_raw(arc) as *mut [T]).unwrap();
+        LockedArc { ptr, key_id }
+    }
+
+    /// Converts the vector into a [`LockedBox<[T; N]>`](LockedBox) with the
+    /// same [`KeyId`], shrinking its allocation if necessary. Returns the
+    /// vector unchanged if its length is not `N`.
+    #[inline]
+    pub fn try_into_boxed_array<K: ?Sized + Key, const N: usize>(
+        self,
+        key: &mut K,
+    ) -> Result<LockedBox<[T; N]>, Self> {
+        check_id(key.id(), self.key_id);
+        if self.len != N {
+            return Err(self);
+        }
+        let key_id = self.key_id;
+        let vec = unsafe { self.raw_unlock(key) };
+        let Ok(b): Result<Box<[T; N]>, _> = vec.into_boxed_slice().try_into() else {
+            unreachable!("length should be N");
+        };
+        let ptr = NonNull::new(Box::into_raw(b)).unwrap();
+        Ok(LockedBox { ptr, key_id })
+    }
+
+    #[inline]
+    pub fn into_raw_parts(self) -> (NonNull<T>, usize, usize, KeyId) {
+        (self.ptr, self.len, self.capacity, self.key_id)
+    }
+
+    /// # Safety
+    ///
+    /// `ptr`, `len`, `capacity`, and `key_id` must have been obtained from a
+    /// previous call to [`LockedVec::into_raw_parts()`].
+    #[inline]
+    pub unsafe fn from_raw_parts(
+        ptr: NonNull<T>,
+        len: usize,
+        capacity: usize,
+        key_id: KeyId,
+    ) -> Self {
+        Self {
+            ptr,
+            len,
+            capacity,
+            key_id,
+        }
+    }
+}
+
+impl LockedVec<u8> {
+    /// Converts the bytes into a [`LockedString`] with the same [`KeyId`], or
+    /// returns them back if they are not valid UTF-8.
+    #[inline]
+    pub fn into_locked_string<K: ?Sized + Key>(
+        self,
+        key: &mut K,
+    ) -> Result<LockedString, (Self, Utf8Error)> {
+        match str::from_utf8(self.get(key)) {
+            Ok(_) => Ok(LockedString { inner: self }),
+            Err(e) => Err((self, e)),
+        }
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+impl<T: Pod> LockedVec<T> {
+    /// Reinterprets the vector as a vector of `U` with the same [`KeyId`], as
+    /// in [`bytemuck::allocation::try_cast_vec()`], or returns it back if the
+    /// cast is not possible.
+    ///
+    /// Since the allocation must be freed with its original alignment, the
+    /// cast fails unless `T` and `U` have the same alignment, and the length
+    /// and capacity in bytes are multiples of the size of `U`.
+    #[inline]
+    pub fn try_cast_vec<U: Pod, K: ?Sized + Key>(self, key: &mut K) -> Result<LockedVec<U>, Self> {
+        check_id(key.id(), self.key_id);
+        let key_id = self.key_id;
+        let vec = unsafe { Vec::from_raw_parts(self.ptr.as_ptr(), self.len, self.capacity) };
+        match bytemuck::allocation::try_cast_vec(vec) {
+            Ok(vec) => Ok(LockedVec::from_vec(vec, key_id)),
+            Err((_, vec)) => Err(Self::from_vec(vec, key_id)),
+        }
+    }
+}
+
+/// An iterator over elements removed from a [`LockedVec`], created by
+/// [`LockedVec::drain()`].
+pub struct LockedDrain<'k, T> {
+    locked: &'k mut LockedVec<T>,
+    front: usize,
+    back: usize,
+    tail_start: usize,
+    tail_len: usize,
+    _key: PhantomData<&'k mut ()>,
+}
+
+impl<T: Debug> Debug for LockedDrain<'_, T> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LockedDrain")
+            .field(&self.as_slice())
+            .finish()
+    }
+}
+
+impl<T> Iterator for LockedDrain<'_, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        if self.front == self.back {
+            return None;
+        }
+        let value = unsafe { self.locked.ptr.add(self.front).read() };
+        self.front += 1;
+        Some(value)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for LockedDrain<'_, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        Some(unsafe { self.locked.ptr.add(self.back).read() })
+    }
+}
+
+impl<T> ExactSizeIterator for LockedDrain<'_, T> {}
+
+impl<T> FusedIterator for LockedDrain<'_, T> {}
+
+impl<T> Drop for LockedDrain<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        struct MoveTail<'a, 'k, T>(&'a mut LockedDrain<'k, T>);
+        impl<T> Drop for MoveTail<'_, '_, T> {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                let locked = &mut *drain.locked;
+                unsafe {
+                    let src = locked.ptr.add(drain.tail_start);
+                    let dst = locked.ptr.add(locked.len);
+                    ptr::copy(src.as_ptr(), dst.as_ptr(), drain.tail_len);
+                }
+                locked.len += drain.tail_len;
+            }
+        }
+        let guard = MoveTail(self);
+        let remaining = guard.0.as_mut_slice() as *mut [T];
+        guard.0.front = guard.0.back;
+        unsafe { ptr::drop_in_place(remaining) };
+    }
+}
+
+impl<T> LockedDrain<'_, T> {
+    /// Returns the remaining elements of the iterator.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        let ptr = unsafe { self.locked.ptr.add(self.front) };
+        unsafe { slice::from_raw_parts(ptr.as_ptr(), self.back - self.front) }
+    }
+
+    #[inline]
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        let ptr = unsafe { self.locked.ptr.add(self.front) };
+        unsafe { slice::from_raw_parts_mut(ptr.as_ptr(), self.back - self.front) }
+    }
+}
+
+impl<T, const N: usize> LockedVec<[T; N]> {
+    #[inline]
+    pub fn as_flattened<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [T] {
+        self.get(key).as_flattened()
+    }
+
+    #[inline]
+    pub fn as_flattened_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut [T] {
+        self.get_mut(key).as_flattened_mut()
+    }
+}
+
+#[derive(Debug)]
+pub struct LockedString {
+    inner: LockedVec<u8>,
+}
+
+struct StringMut<'a> {
+    string: ManuallyDrop<String>,
+    locked: &'a mut LockedVec<u8>,
+}
+
+impl Deref for StringMut<'_> {
+    type Target = String;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.string
+    }
+}
+
+impl DerefMut for StringMut<'_> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.string
+    }
+}
+
+impl Drop for StringMut<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        let vec = unsafe { self.string.as_mut_vec() };
+        self.locked.ptr = NonNull::new(vec.as_mut_ptr()).unwrap();
+        self.locked.len = vec.len();
+        self.locked.capacity = vec.capacity();
+    }
+}
+
+impl Locked for LockedString {
+    type Unlocked = String;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.inner.key_id()
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(s: Self::Unlocked, key: &K) -> Self {
+        let vec = s.into_bytes();
+        let inner = unsafe { LockedVec::raw_lock(vec, key) };
+        Self { inner }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        let vec = unsafe { self.inner.raw_unlock(key) };
+        unsafe { String::from_utf8_unchecked(vec) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        let inner = unsafe { self.inner.raw_clone() };
+        Self { inner }
+    }
+}
+
+impl LockedString {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k str {
+        let bytes = self.inner.get(key);
+        unsafe { str::from_utf8_unchecked(bytes) }
+    }
+
+    #[inline]
+    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut str {
+        let bytes = self.inner.get_mut(key);
+        unsafe { str::from_utf8_unchecked_mut(bytes) }
+    }
+
+    /// Returns the substring in the given byte range, or [`None`] if either
+    /// end of the range is out of bounds or not on a `char` boundary.
+    #[inline]
+    pub fn get_str_range<'k, K: ?Sized + Key, R: RangeBounds<usize>>(
+        &self,
+        key: &'k K,
+        range: R,
+    ) -> Option<&'k str> {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        self.get(key).get(bounds)
+    }
+
+    #[inline]
+    pub fn get_bytes<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [u8] {
+        self.inner.get(key)
+    }
+
+    /// # Safety
+    ///
+    /// The contents of the slice must be valid UTF-8 when the borrow ends.
+    #[inline]
+    pub unsafe fn get_bytes_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut [u8] {
+        self.inner.get_mut(key)
+    }
+
+    #[inline]
+    pub fn get_bytes_buf<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [MaybeUninit<u8>] {
+        self.inner.get_buf(key)
+    }
+
+    /// # Safety
+    ///
+    /// The first [`len()`](Self::len) bytes of the buffer must be valid UTF-8
+    /// when the borrow ends.
+    #[inline]
+    pub unsafe fn get_bytes_buf_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+    ) -> &'k mut [MaybeUninit<u8>] {
+        self.inner.get_buf_mut(key)
+    }
+
+    #[inline]
+    fn string_mut<K: ?Sized + Key>(&mut self, key: &mut K) -> StringMut<'_> {
+        let inner = &mut self.inner;
+        check_id(key.id(), inner.key_id);
+        let string =
+            unsafe { String::from_raw_parts(inner.ptr.as_ptr(), inner.len, inner.capacity) };
+        StringMut {
+            string: ManuallyDrop::new(string),
+            locked: inner,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `new_len` does not lie on a `char` boundary.
+    #[inline]
+    pub fn truncate<K: ?Sized + Key>(&mut self, key: &mut K, new_len: usize) {
+        self.string_mut(key).truncate(new_len);
+    }
+
+    #[inline]
+    pub fn clear<K: ?Sized + Key>(&mut self, key: &mut K) {
+        self.string_mut(key).clear();
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `idx` is greater than the length or does not lie on a `char`
+    /// boundary.
+    #[inline]
+    pub fn insert_str<K: ?Sized + Key>(&mut self, key: &mut K, idx: usize, s: &str) {
+        self.string_mut(key).insert_str(idx, s);
+    }
+
+    #[inline]
+    pub fn truncate_chars<K: ?Sized + Key>(&mut self, key: &mut K, max_chars: usize) {
+        let mut string = self.string_mut(key);
+        if let Some((new_len, _)) = string.char_indices().nth(max_chars) {
+            string.truncate(new_len);
+        }
+    }
+
+    /// Converts the string into its underlying bytes, with the same
+    /// [`KeyId`].
+    #[inline]
+    pub fn into_locked_bytes(self) -> LockedVec<u8> {
+        self.inner
+    }
+
+    #[inline]
+    pub fn push<K: ?Sized + Key>(&mut self, key: &mut K, c: char) {
+        self.string_mut(key).push(c);
+    }
+
+    #[inline]
+    pub fn push_str<K: ?Sized + Key>(&mut self, key: &mut K, s: &str) {
+        self.string_mut(key).push_str(s);
+    }
+
+    /// Truncates the string to at most `max_graphemes` extended grapheme
+    /// clusters, so that no cluster is split.
+    #[cfg(feature = "unicode-segmentation")]
+    #[inline]
+    pub fn truncate_graphemes<K: ?Sized + Key>(&mut self, key: &mut K, max_graphemes: usize) {
+        let mut string = self.string_mut(key);
+        if let Some((new_len, _)) = string.grapheme_indices(true).nth(max_graphemes) {
+            string.truncate(new_len);
+        }
+    }
+
+    #[inline]
+    pub fn push_locked_str<K1: ?Sized + Key, K2: ?Sized + Key>(
+        &mut self,
+        my_key: &mut K1,
+        other: &LockedString,
+        other_key: &K2,
+    ) {
+        let other = other.get(other_key);
+        self.string_mut(my_key).push_str(other);
+    }
+
+    #[inline]
+    pub fn into_raw_parts(self) -> (NonNull<u8>, usize, usize, KeyId) {
+        self.inner.into_raw_parts()
+    }
+
+    /// # Safety
+    ///
+    /// `ptr`, `len`, `capacity`, and `key_id` must have been obtained from a
+    /// previous call to [`LockedString::into_raw_parts()`].
+    #[inline]
+    pub unsafe fn from_raw_parts(
+        ptr: NonNull<u8>,
+        len: usize,
+        capacity: usize,
+        key_id: KeyId,
+    ) -> Self {
+        let inner = unsafe { LockedVec::from_raw_parts(ptr, len, capacity, key_id) };
+        Self { inner }
+    }
+}
+
+#[derive(Debug)]
+pub struct LockedCString {
+    inner: LockedVec<u8>,
+}
+
+impl Locked for LockedCString {
+    type Unlocked = CString;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.inner.key_id()
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(s: Self::Unlocked, key: &K) -> Self {
+        let vec = s.into_bytes_with_nul();
+        let inner = unsafe { LockedVec::raw_lock(vec, key) };
+        Self { inner }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        let vec = unsafe { self.inner.raw_unlock(key) };
+        unsafe { CString::from_vec_with_nul_unchecked(vec) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        let inner = unsafe { self.inner.raw_clone() };
+        Self { inner }
+    }
+}
+
+impl LockedCString {
+    #[inline]
+    pub fn count_bytes(&self) -> usize {
+        self.inner.len() - 1
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count_bytes() == 0
+    }
+
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k CStr {
+        let bytes = self.inner.get(key);
+        unsafe { CStr::from_bytes_with_nul_unchecked(bytes) }
+    }
+
+    #[inline]
+    pub fn into_raw_parts(self) -> (NonNull<u8>, usize, usize, KeyId) {
+        self.inner.into_raw_parts()
+    }
+
+    /// # Safety
+    ///
+    /// `ptr`, `len`, `capacity`, and `key_id` must have been obtained from a
+    /// previous call to [`LockedCString::into_raw_parts()`].
+    #[inline]
+    pub unsafe fn from_raw_parts(
+        ptr: NonNull<u8>,
+        len: usize,
+        capacity: usize,
+        key_id: KeyId,
+    ) -> Self {
+        let inner = unsafe { LockedVec::from_raw_parts(ptr, len, capacity, key_id) };
+        Self { inner }
+    }
+
+    /// Like [`from_raw_parts()`](Self::from_raw_parts), but checks that the
+    /// bytes end with a nul byte and contain no other nul bytes.
+    ///
+    /// # Safety
+    ///
+    /// `ptr`, `len`, `capacity`, and `key_id` must have been obtained from a
+    /// previous call to `into_raw_parts()` on a [`LockedCString`],
+    /// [`LockedString`], or [`LockedVec<u8>`].
+    #[inline]
+    pub unsafe fn try_from_raw_parts(
+        ptr: NonNull<u8>,
+        len: usize,
+        capacity: usize,
+        key_id: KeyId,
+    ) -> Result<Self, FromBytesWithNulError> {
+        let bytes = unsafe { slice::from_raw_parts(ptr.as_ptr(), len) };
+        CStr::from_bytes_with_nul(bytes)?;
+        Ok(unsafe { Self::from_raw_parts(ptr, len, capacity, key_id) })
+    }
+}
+
+/// A locked [`VecDeque`].
+///
+/// Since [`VecDeque`] does not expose its raw parts, the deque is stored in its
+/// own heap allocation while locked.
+#[derive(Debug)]
+pub struct LockedVecDeque<T> {
+    ptr: NonNull<VecDeque<T>>,
+    key_id: KeyId,
+}
+
+unsafe impl<T: Send> Send for LockedVecDeque<T> {}
+
+unsafe impl<T: Send + Sync> Sync for LockedVecDeque<T> {}
+
+impl<T> Locked for LockedVecDeque<T> {
+    type Unlocked = VecDeque<T>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(deque: Self::Unlocked, key: &K) -> Self {
+        let key_id = key.id();
+        let ptr = NonNull::new(Box::into_raw(Box::new(deque))).unwrap();
+        Self { ptr, key_id }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        check_id(key.id(), self.key_id);
+        *unsafe { Box::from_raw(self.ptr.as_ptr()) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+impl<T> LockedVecDeque<T> {
+    /// Returns the contents of the deque as a pair of slices, in order.
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> (&'k [T], &'k [T]) {
+        check_id(key.id(), self.key_id);
+        let deque = unsafe { self.ptr.as_ref() };
+        deque.as_slices()
+    }
+
+    /// Returns the contents of the deque as a pair of mutable slices, in
+    /// order.
+    #[inline]
+    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> (&'k mut [T], &'k mut [T]) {
+        check_id(key.id(), self.key_id);
+        let deque = unsafe { { self.ptr }.as_mut() };
+        deque.as_mut_slices()
+    }
+
+    #[inline]
+    fn deque_mut<K: ?Sized + Key>(&mut self, key: &mut K) -> &mut VecDeque<T> {
+        check_id(key.id(), self.key_id);
+        unsafe { self.ptr.as_mut() }
+    }
+
+    #[inline]
+    pub fn push_back<K: ?Sized + Key>(&mut self, key: &mut K, value: T) {
+        self.deque_mut(key).push_back(value);
+    }
+
+    #[inline]
+    pub fn push_front<K: ?Sized + Key>(&mut self, key: &mut K, value: T) {
+        self.deque_mut(key).push_front(value);
+    }
+
+    #[inline]
+    pub fn pop_back<K: ?Sized + Key>(&mut self, key: &mut K) -> Option<T> {
+        self.deque_mut(key).pop_back()
+    }
+
+    #[inline]
+    pub fn pop_front<K: ?Sized + Key>(&mut self, key: &mut K) -> Option<T> {
+        self.deque_mut(key).pop_front()
+    }
+
+    #[inline]
+    pub fn make_contiguous<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut [T] {
+        check_id(key.id(), self.key_id);
+        let deque = unsafe { { self.ptr }.as_mut() };
+        deque.make_contiguous()
+    }
+}
+
+/// A locked [`BTreeMap`], stored in its own heap allocation while locked.
+#[derive(Debug)]
+pub struct LockedBTreeMap<K2, V> {
+    ptr: NonNull<BTreeMap<K2, V>>,
+    key_id: KeyId,
+}
+
+unsafe impl<K2: Send, V: Send> Send for LockedBTreeMap<K2, V> {}
+
+unsafe impl<K2: Send + Sync, V: Send + Sync> Sync for LockedBTreeMap<K2, V> {}
+
+impl<K2, V> Locked for LockedBTreeMap<K2, V> {
+    type Unlocked = BTreeMap<K2, V>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(map: Self::Unlocked, key: &K) -> Self {
+        let key_id = key.id();
+        let ptr = NonNull::new(Box::into_raw(Box::new(map))).unwrap();
+        Self { ptr, key_id }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        check_id(key.id(), self.key_id);
+        *unsafe { Box::from_raw(self.ptr.as_ptr()) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+impl<K2, V> LockedBTreeMap<K2, V> {
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k BTreeMap<K2, V> {
+        check_id(key.id(), self.key_id);
+        unsafe { self.ptr.as_ref() }
+    }
+
+    #[inline]
+    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut BTreeMap<K2, V> {
+        check_id(key.id(), self.key_id);
+        unsafe { { self.ptr }.as_mut() }
+    }
+
+    /// Returns the entry for `k2` in the map, if any.
+    #[inline]
+    pub fn get_entry<'k, K: ?Sized + Key, Q: ?Sized + Ord>(
+        &self,
+        key: &'k K,
+        k2: &Q,
+    ) -> Option<(&'k K2, &'k V)>
+    where
+        K2: Borrow<Q> + Ord,
+    {
+        self.get(key).get_key_value(k2)
+    }
+
+    #[inline]
+    pub fn insert<K: ?Sized + Key>(&mut self, key: &mut K, k2: K2, value: V) -> Option<V>
+    where
+        K2: Ord,
+    {
+        self.get_mut(key).insert(k2, value)
+    }
+
+    #[inline]
+    pub fn remove<K: ?Sized + Key, Q: ?Sized + Ord>(&mut self, key: &mut K, k2: &Q) -> Option<V>
+    where
+        K2: Borrow<Q> + Ord,
+    {
+        self.get_mut(key).remove(k2)
+    }
+}
+
+/// A locked [`HashMap`], stored in its own heap allocation while locked.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct LockedHashMap<K2, V, S = RandomState> {
+    ptr: NonNull<HashMap<K2, V, S>>,
+    key_id: KeyId,
+}
+
+#[cfg(feature = "std")]
+unsafe impl<K2: Send, V: Send, S: Send> Send for LockedHashMap<K2, V, S> {}
+
+#[cfg(feature = "std")]
+unsafe impl<K2: Send + Sync, V: Send + Sync, S: Send + Sync> Sync for LockedHashMap<K2, V, S> {}
+
+#[cfg(feature = "std")]
+impl<K2, V, S> Locked for LockedHashMap<K2, V, S> {
+    type Unlocked = HashMap<K2, V, S>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(map: Self::Unlocked, key: &K) -> Self {
+        let key_id = key.id();
+        let ptr = NonNull::new(Box::into_raw(Box::new(map))).unwrap();
+        Self { ptr, key_id }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        check_id(key.id(), self.key_id);
+        *unsafe { Box::from_raw(self.ptr.as_ptr()) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K2, V, S> LockedHashMap<K2, V, S> {
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k HashMap<K2, V, S> {
+        check_id(key.id(), self.key_id);
+        unsafe { self.ptr.as_ref() }
+    }
+
+    #[inline]
+    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut HashMap<K2, V, S> {
+        check_id(key.id(), self.key_id);
+        unsafe { { self.ptr }.as_mut() }
+    }
+
+    #[inline]
+    pub fn contains_key<K: ?Sized + Key, Q: ?Sized + Hash + Eq>(&self, key: &K, k2: &Q) -> bool
+    where
+        K2: Borrow<Q> + Hash + Eq,
+        S: BuildHasher,
+    {
+        self.get(key).contains_key(k2)
+    }
+
+    #[inline]
+    pub fn insert<K: ?Sized + Key>(&mut self, key: &mut K, k2: K2, value: V) -> Option<V>
+    where
+        K2: Hash + Eq,
+        S: BuildHasher,
+    {
+        self.get_mut(key).insert(k2, value)
+    }
+
+    #[inline]
+    pub fn remove<K: ?Sized + Key, Q: ?Sized + Hash + Eq>(
+        &mut self,
+        key: &mut K,
+        k2: &Q,
+    ) -> Option<V>
+    where
+        K2: Borrow<Q> + Hash + Eq,
+        S: BuildHasher,
+    {
+        self.get_mut(key).remove(k2)
+    }
+}
+
+#[derive(Debug)]
+pub struct LockedRc<T: ?Sized> {
+    ptr: NonNull<T>,
+    key_id: KeyId,
+}
+
+impl<T: ?Sized> Locked for LockedRc<T> {
+    type Unlocked = Rc<T>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(rc: Self::Unlocked, key: &K) -> Self {
+        let key_id = key.id();
+        let ptr = NonNull::new(Rc::into_raw(rc) as *mut T).unwrap();
+        Self { ptr, key_id }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        check_id(key.id(), self.key_id);
+        unsafe { Rc::from_raw(self.ptr.as_ptr()) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+impl<T: ?Sized> LockedRc<T> {
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T {
+        check_id(key.id(), self.key_id);
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Returns a mutable reference to the contents if there are no other
+    /// [`Rc`] or weak pointers to the same allocation, as in
+    /// [`Rc::get_mut()`].
+    ///
+    /// No new pointers can be created while the reference is live, since
+    /// [`clone()`](Self::clone) and [`downgrade()`](Self::downgrade) require
+    /// the key:
+    ///
+    /// ```compile_fail,E0502
+    /// # use owning_key::{ForgettingKey, LockedRc};
+    /// # use std::rc::Rc;
+    /// let mut key = ForgettingKey::new();
+    /// let locked: LockedRc<i32> = key.lock(Rc::new(1));
+    /// let value = locked.get_mut(&mut key).unwrap();
+    /// let other = locked.clone(&key);
+    /// *value = 2;
+    /// ```
+    ///
+    /// ```compile_fail,E0502
+    /// # use owning_key::{ForgettingKey, LockedRc};
+    /// # use std::rc::Rc;
+    /// let mut key = ForgettingKey::new();
+    /// let locked: LockedRc<i32> = key.lock(Rc::new(1));
+    /// let value = locked.get_mut(&mut key).unwrap();
+    /// let other = locked.downgrade(&key);
+    /// *value = 2;
+    /// ```
+    #[inline]
+    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> Option<&'k mut T> {
+        check_id(key.id(), self.key_id);
+        let mut rc = ManuallyDrop::new(unsafe { Rc::from_raw(self.ptr.as_ptr()) });
+        let ptr: *mut T = Rc::get_mut(&mut rc)?;
+        unsafe { Some(&mut *ptr) }
+    }
+
+    #[inline]
+    pub fn try_get<'k, K: ?Sized + Key>(&self, key: &'k K) -> Result<&'k T, WrongKeyError> {
+        try_check_id(key.id(), self.key_id)?;
+        unsafe { Ok(self.ptr.as_ref()) }
+    }
+
+    /// Like [`get_mut()`](Self::get_mut), but returns an error instead of
+    /// panicking if the key is wrong.
+    #[inline]
+    pub fn try_get_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+    ) -> Result<Option<&'k mut T>, WrongKeyError> {
+        try_check_id(key.id(), self.key_id)?;
+        let mut rc = ManuallyDrop::new(unsafe { Rc::from_raw(self.ptr.as_ptr()) });
+        let ptr: *mut T = match Rc::get_mut(&mut rc) {
+            Some(ptr) => ptr,
+            None => return Ok(None),
+        };
+        unsafe { Ok(Some(&mut *ptr)) }
+    }
+
+    #[inline]
+    pub fn clone<K: ?Sized + Key>(&self, key: &K) -> Rc<T> {
+        check_id(key.id(), self.key_id);
+        unsafe {
+            Rc::increment_strong_count(self.ptr.as_ptr());
+            Rc::from_raw(self.ptr.as_ptr())
+        }
+    }
+
+    #[inline]
+    pub fn downgrade<K: ?Sized + Key>(&self, key: &K) -> rc::Weak<T> {
+        check_id(key.id(), self.key_id);
+        let rc = ManuallyDrop::new(unsafe { Rc::from_raw(self.ptr.as_ptr()) });
+        Rc::downgrade(&rc)
+    }
+
+    #[inline]
+    pub fn strong_count<K: ?Sized + Key>(&self, key: &K) -> usize {
+        check_id(key.id(), self.key_id);
+        let rc = ManuallyDrop::new(unsafe { Rc::from_raw(self.ptr.as_ptr()) });
+        Rc::strong_count(&rc)
+    }
+
+    #[inline]
+    pub fn weak_count<K: ?Sized + Key>(&self, key: &K) -> usize {
+        check_id(key.id(), self.key_id);
+        let rc = ManuallyDrop::new(unsafe { Rc::from_raw(self.ptr.as_ptr()) });
+        Rc::weak_count(&rc)
+    }
+
+    /// Returns the contents if this is the only strong pointer to the
+    /// allocation, as in [`Rc::try_unwrap()`]. Otherwise, returns this value
+    /// unchanged.
+    #[inline]
+    pub fn try_unwrap<K: ?Sized + Key>(self, key: &mut K) -> Result<T, Self>
+    where
+        T: Sized,
+    {
+        check_id(key.id(), self.key_id);
+        let rc = unsafe { Rc::from_raw(self.ptr.as_ptr()) };
+        Rc::try_unwrap(rc).map_err(|rc| {
+            let ptr = NonNull::new(Rc::into_raw(rc) as *mut T).unwrap();
+            Self { ptr, ..self }
+        })
+    }
+
+    /// Returns whether both values point to the same allocation, as in
+    /// [`Rc::ptr_eq()`]. This does not compare the [`KeyId`]s, since separate
+    /// clones of one [`Rc`] may be locked with different keys.
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.ptr.cast::<()>() == other.ptr.cast::<()>()
+    }
+
+    /// Returns the pointer and [`KeyId`] of the value. The pointer is one
+    /// returned by [`Rc::into_raw()`], and it owns a strong reference.
+    #[inline]
+    pub fn into_raw_parts(self) -> (NonNull<T>, KeyId) {
+        (self.ptr, self.key_id)
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` and `key_id` must have been obtained from a previous call to
+    /// [`LockedRc::into_raw_parts()`].
+    #[inline]
+    pub unsafe fn from_raw_parts(ptr: NonNull<T>, key_id: KeyId) -> Self {
+        Self { ptr, key_id }
+    }
+}
+
+impl<T> LockedRc<[T]> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ptr.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ptr.len() == 0
+    }
+}
+
+#[derive(Debug)]
+pub struct LockedPinRc<T: ?Sized> {
+    inner: LockedRc<T>,
+}
+
+impl<T: ?Sized> Locked for LockedPinRc<T> {
+    type Unlocked = Pin<Rc<T>>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.inner.key_id()
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(rc: Self::Unlocked, key: &K) -> Self {
+        let rc = unsafe { Pin::into_inner_unchecked(rc) };
+        let inner = unsafe { LockedRc::raw_lock(rc, key) };
+        Self { inner }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        let rc = unsafe { self.inner.raw_unlock(key) };
+        unsafe { Pin::new_unchecked(rc) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        let inner = unsafe { self.inner.raw_clone() };
+        Self { inner }
+    }
+}
+
+impl<T: ?Sized> LockedPinRc<T> {
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> Pin<&'k T> {
+        unsafe { Pin::new_unchecked(self.inner.get(key)) }
+    }
+
+    #[inline]
+    pub fn clone<K: ?Sized + Key>(&self, key: &K) -> Pin<Rc<T>> {
+        unsafe { Pin::new_unchecked(self.inner.clone(key)) }
+    }
+}
+
+/// A locked [`rc::Weak`]. The pointer may be the dangling sentinel of a
+/// [`rc::Weak`] created with [`rc::Weak::new()`], which is never upgradable.
+///
+/// A [`LockedRc`] and a weak pointer to it can both be kept behind a key, and
+/// a reference cycle through them can be broken with the key:
+///
+/// ```
+/// # #[cfg(feature = "std")]
+/// # fn main() {
+/// # use owning_key::{LocalDroppingKey, LockedRc, LockedWeak};
+/// use std::{cell::RefCell, rc::Rc};
+///
+/// struct Node {
+///     next: RefCell<Option<Rc<Node>>>,
+/// }
+///
+/// let key = LocalDroppingKey::new();
+/// let a = key.lock::<LockedRc<Node>>(Rc::new(Node { next: RefCell::new(None) }));
+/// let b = Rc::new(Node { next: RefCell::new(Some(a.clone(&key))) });
+/// *a.get(&key).next.borrow_mut() = Some(b);
+/// let weak = key.lock::<LockedWeak<Node>>(a.downgrade(&key));
+/// assert!(weak.upgrade(&key).is_some());
+///
+/// let probe = a.downgrade(&key);
+/// a.get(&key).next.borrow_mut().take();
+/// drop(key);
+/// assert!(probe.upgrade().is_none());
+/// # }
+/// # #[cfg(not(feature = "std"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct LockedWeak<T: ?Sized> {
+    ptr: NonNull<T>,
+    key_id: KeyId,
+}
+
+impl<T: ?Sized> Locked for LockedWeak<T> {
+    type Unlocked = rc::Weak<T>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(weak: Self::Unlocked, key: &K) -> Self {
+        let key_id = key.id();
+        let ptr = NonNull::new(rc::Weak::into_raw(weak) as *mut T).unwrap();
+        Self { ptr, key_id }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        check_id(key.id(), self.key_id);
+        unsafe { rc::Weak::from_raw(self.ptr.as_ptr()) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+impl<T: ?Sized> LockedWeak<T> {
+    /// Attempts to upgrade the weak pointer, as in [`rc::Weak::upgrade()`].
+    /// The weak pointer itself is left in place.
+    #[inline]
+    pub fn upgrade<K: ?Sized + Key>(&self, key: &K) -> Option<Rc<T>> {
+        check_id(key.id(), self.key_id);
+        let weak = ManuallyDrop::new(unsafe { rc::Weak::from_raw(self.ptr.as_ptr()) });
+        weak.upgrade()
+    }
+}
+
+/// A locked [`Arc`].
+///
+/// Like [`Arc`], this is [`Send`] and [`Sync`] when `T` is both [`Send`] and
+/// [`Sync`]. All reference counting goes through [`Arc`] itself, so it is
+/// atomic even when other clones live on other threads.
+#[derive(Debug)]
+pub struct LockedArc<T: ?Sized> {
+    ptr: NonNull<T>,
+    key_id: KeyId,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Send for LockedArc<T> {}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for LockedArc<T> {}
+
+impl<T: ?Sized> Locked for LockedArc<T> {
+    type Unlocked = Arc<T>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(arc: Self::Unlocked, key: &K) -> Self {
+        let key_id = key.id();
+        let ptr = NonNull::new(Arc::into_raw(arc) as *mut T).unwrap();
+        Self { ptr, key_id }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        check_id(key.id(), self.key_id);
+        unsafe { Arc::from_raw(self.ptr.as_ptr()) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+impl<T: ?Sized> LockedArc<T> {
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T {
+        check_id(key.id(), self.key_id);
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Returns a mutable reference to the contents if there are no other
+    /// [`Arc`] or weak pointers to the same allocation, as in
+    /// [`Arc::get_mut()`].
+    ///
+    /// No new pointers can be created while the reference is live, since
+    /// [`clone()`](Self::clone) and [`downgrade()`](Self::downgrade) require
+    /// the key:
+    ///
+    /// ```compile_fail,E0502
+    /// # use owning_key::{ForgettingKey, LockedArc};
+    /// # use std::sync::Arc;
+    /// let mut key = ForgettingKey::new();
+    /// let locked: LockedArc<i32> = key.lock(Arc::new(1));
+    /// let value = locked.get_mut(&mut key).unwrap();
+    /// let other = locked.clone(&key);
+    /// *value = 2;
+    /// ```
+    ///
+    /// ```compile_fail,E0502
+    /// # use owning_key::{ForgettingKey, LockedArc};
+    /// # use std::sync::Arc;
+    /// let mut key = ForgettingKey::new();
+    /// let locked: LockedArc<i32> = key.lock(Arc::new(1));
+    /// let value = locked.get_mut(&mut key).unwrap();
+    /// let other = locked.downgrade(&key);
+    /// *value = 2;
+    /// ```
+    #[inline]
+    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> Option<&'k mut T> {
+        check_id(key.id(), self.key_id);
+        let mut arc = ManuallyDrop::new(unsafe { Arc::from_raw(self.ptr.as_ptr()) });
+        let ptr: *mut T = Arc::get_mut(&mut arc)?;
+        unsafe { Some(&mut *ptr) }
+    }
+
+    #[inline]
+    pub fn try_get<'k, K: ?Sized + Key>(&self, key: &'k K) -> Result<&'k T, WrongKeyError> {
+        try_check_id(key.id(), self.key_id)?;
+        unsafe { Ok(self.ptr.as_ref()) }
+    }
+
+    /// Like [`get_mut()`](Self::get_mut), but returns an error instead of
+    /// panicking if the key is wrong.
+    #[inline]
+    pub fn try_get_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+    ) -> Result<Option<&'k mut T>, WrongKeyError> {
+        try_check_id(key.id(), self.key_id)?;
+        let mut arc = ManuallyDrop::new(unsafe { Arc::from_raw(self.ptr.as_ptr()) });
+        let ptr: *mut T = match Arc::get_mut(&mut arc) {
+            Some(ptr) => ptr,
+            None => return Ok(None),
+        };
+        unsafe { Ok(Some(&mut *ptr)) }
+    }
+
+    #[inline]
+    pub fn clone<K: ?Sized + Key>(&self, key: &K) -> Arc<T> {
+        check_id(key.id(), self.key_id);
+        unsafe {
+            Arc::increment_strong_count(self.ptr.as_ptr());
+            Arc::from_raw(self.ptr.as_ptr())
+        }
+    }
+
+    #[inline]
+    pub fn downgrade<K: ?Sized + Key>(&self, key: &K) -> sync::Weak<T> {
+        check_id(key.id(), self.key_id);
+        let arc = ManuallyDrop::new(unsafe { Arc::from_raw(self.ptr.as_ptr()) });
+        Arc::downgrade(&arc)
+    }
+
+    #[inline]
+    pub fn strong_count<K: ?Sized + Key>(&self, key: &K) -> usize {
+        check_id(key.id(), self.key_id);
+        let arc = ManuallyDrop::new(unsafe { Arc::from_raw(self.ptr.as_ptr()) });
+        Arc::strong_count(&arc)
+    }
+
+    #[inline]
+    pub fn weak_count<K: ?Sized + Key>(&self, key: &K) -> usize {
+        check_id(key.id(), self.key_id);
+        let arc = ManuallyDrop::new(unsafe { Arc::from_raw(self.ptr.as_ptr()) });
+        Arc::weak_count(&arc)
+    }
+
+    /// Returns the contents if this is the only strong pointer to the
+    /// allocation, as in [`Arc::try_unwrap()`]. Otherwise, returns this value
+    /// unchanged.
+    #[inline]
+    pub fn try_unwrap<K: ?Sized + Key>(self, key: &mut K) -> Result<T, Self>
+    where
+        T: Sized,
+    {
+        check_id(key.id(), self.key_id);
+        let arc = unsafe { Arc::from_raw(self.ptr.as_ptr()) };
+        Arc::try_unwrap(arc).map_err(|arc| {
+            let ptr = NonNull::new(Arc::into_raw(arc) as *mut T).unwrap();
+            Self { ptr, ..self }
+        })
+    }
+
+    /// Returns whether both values point to the same allocation, as in
+    /// [`Arc::ptr_eq()`]. This does not compare the [`KeyId`]s, since separate
+    /// clones of one [`Arc`] may be locked with different keys.
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.ptr.cast::<()>() == other.ptr.cast::<()>()
+    }
+
+    /// Returns the pointer and [`KeyId`] of the value. The pointer is one
+    /// returned by [`Arc::into_raw()`], and it owns a strong reference.
+    #[inline]
+    pub fn into_raw_parts(self) -> (NonNull<T>, KeyId) {
+        (self.ptr, self.key_id)
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` and `key_id` must have been obtained from a previous call to
+    /// [`LockedArc::into_raw_parts()`].
+    #[inline]
+    pub unsafe fn from_raw_parts(ptr: NonNull<T>, key_id: KeyId) -> Self {
+        Self { ptr, key_id }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> LockedArc<Mutex<T>> {
+    /// Locks the mutex in the [`Arc`], blocking until it is available.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mutex is poisoned.
+    #[inline]
+    pub fn lock_inner<'k, K: ?Sized + Key>(&self, key: &'k K) -> MutexGuard<'k, T> {
+        self.get(key).lock().unwrap()
+    }
+}
+
+impl<T> LockedArc<[T]> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ptr.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ptr.len() == 0
+    }
+}
+
+#[derive(Debug)]
+pub struct LockedPinArc<T: ?Sized> {
+    inner: LockedArc<T>,
+}
+
+impl<T: ?Sized> Locked for LockedPinArc<T> {
+    type Unlocked = Pin<Arc<T>>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.inner.key_id()
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(arc: Self::Unlocked, key: &K) -> Self {
+        let arc = unsafe { Pin::into_inner_unchecked(arc) };
+        let inner = unsafe { LockedArc::raw_lock(arc, key) };
+        Self { inner }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        let arc = unsafe { self.inner.raw_unlock(key) };
+        unsafe { Pin::new_unchecked(arc) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        let inner = unsafe { self.inner.raw_clone() };
+        Self { inner }
+    }
+}
+
+impl<T: ?Sized> LockedPinArc<T> {
+    #[inline]
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> Pin<&'k T> {
+        unsafe { Pin::new_unchecked(self.inner.get(key)) }
+    }
+
+    #[inline]
+    pub fn clone<K: ?Sized + Key>(&self, key: &K) -> Pin<Arc<T>> {
+        unsafe { Pin::new_unchecked(self.inner.clone(key)) }
+    }
+}
+
+/// A locked [`sync::Weak`]. The pointer may be the dangling sentinel of a
+/// [`sync::Weak`] created with [`sync::Weak::new()`], which is never upgradable.
+#[derive(Debug)]
+pub struct LockedWeakArc<T: ?Sized> {
+    ptr: NonNull<T>,
+    key_id: KeyId,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Send for LockedWeakArc<T> {}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for LockedWeakArc<T> {}
+
+impl<T: ?Sized> Locked for LockedWeakArc<T> {
+    type Unlocked = sync::Weak<T>;
+
+    #[inline]
+    fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    #[inline]
+    unsafe fn raw_lock<K: ?Sized + Key>(weak: Self::Unlocked, key: &K) -> Self {
+        let key_id = key.id();
+        let ptr = NonNull::new(sync::Weak::into_raw(weak) as *mut T).unwrap();
+        Self { ptr, key_id }
+    }
+
+    #[inline]
+    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
+        check_id(key.id(), self.key_id);
+        unsafe { sync::Weak::from_raw(self.ptr.as_ptr()) }
+    }
+
+    #[inline]
+    unsafe fn raw_clone(&self) -> Self {
+        Self { ..*self }
+    }
+}
+
+impl<T: ?Sized> LockedWeakArc<T> {
+    /// Attempts to upgrade the weak pointer, as in [`sync::Weak::upgrade()`].
+    /// The weak pointer itself is left in place.
+    #[inline]
+    pub fn upgrade<K: ?Sized + Key>(&self, key: &K) -> Option<Arc<T>> {
+        check_id(key.id(), self.key_id);
+        let weak = ManuallyDrop::new(unsafe { sync::Weak::from_raw(self.ptr.as_ptr()) });
+        weak.upgrade()
+    }
+}
+
+impl<T: ?Sized> Display for LockedBox<T> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "LockedBox {{ key_id: {} }}", self.key_id)
+    }
+}
+
+impl<T> Display for LockedVec<T> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LockedVec {{ key_id: {}, len: {}, cap: {} }}",
+            self.key_id, self.len, self.capacity,
+        )
+    }
+}
+
+impl Display for LockedString {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LockedString {{ key_id: {}, len: {}, cap: {} }}",
+            self.inner.key_id,
+            self.len(),
+            self.capacity(),
+        )
+    }
+}
+
+impl<T: ?Sized> Display for LockedRc<T> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "LockedRc {{ key_id: {} }}", self.key_id)
+    }
+}
+
+impl<T: ?Sized> Display for LockedArc<T> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "LockedArc {{ key_id: {} }}", self.key_id)
+    }
+}
+
+impl<T: ?Sized> AccessLocked for LockedBox<T> {
+    type AccessTarget = T;
+
+    #[inline]
+    fn access<'k, K: ?Sized + Key>(&'k self, key: &'k K) -> &'k T {
+        self.get(key)
+    }
+
+    #[inline]
+    fn access_mut<'k, K: ?Sized + Key>(&'k mut self, key: &'k mut K) -> &'k mut T {
+        self.get_mut(key)
+    }
+}
+
+impl<T> AccessLocked for LockedVec<T> {
+    type AccessTarget = [T];
+
+    #[inline]
+    fn access<'k, K: ?Sized + Key>(&'k self, key: &'k K) -> &'k [T] {
+        self.get(key)
+    }
+
+    #[inline]
+    fn access_mut<'k, K: ?Sized + Key>(&'k mut self, key: &'k mut K) -> &'k mut [T] {
+        self.get_mut(key)
+    }
+}
+
+impl AccessLocked for LockedString {
+    type AccessTarget = str;
+
+    #[inline]
+    fn access<'k, K: ?Sized + Key>(&'k self, key: &'k K) -> &'k str {
+        self.get(key)
+    }
+
+    #[inline]
+    fn access_mut<'k, K: ?Sized + Key>(&'k mut self, key: &'k mut K) -> &'k mut str {
+        self.get_mut(key)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: ?Sized + Serialize> SerializeLocked for LockedBox<T> {
+    #[inline]
+    fn serialize_locked<K: ?Sized + Key, S: Serializer>(
+        &self,
+        key: &K,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.get(key).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize> SerializeLocked for LockedVec<T> {
+    #[inline]
+    fn serialize_locked<K: ?Sized + Key, S: Serializer>(
+        &self,
+        key: &K,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.get(key).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SerializeLocked for LockedString {
+    #[inline]
+    fn serialize_locked<K: ?Sized + Key, S: Serializer>(
+        &self,
+        key: &K,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.get(key).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: ?Sized + Serialize> SerializeLocked for LockedRc<T> {
+    #[inline]
+    fn serialize_locked<K: ?Sized + Key, S: Serializer>(
+        &self,
+        key: &K,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.get(key).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: ?Sized + Serialize> SerializeLocked for LockedArc<T> {
+    #[inline]
+    fn serialize_locked<K: ?Sized + Key, S: Serializer>(
+        &self,
+        key: &K,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.get(key).serialize(serializer)
+    }
+}
+
+/// Locks each of the listed fields of a [`LockedBox`]'s contents into a
+/// separate [`LockedMut`] with the given key, returning them as a tuple.
+///
+/// The handles mutably borrow the box, which stays locked. Once they are no
+/// longer used, the box can be unlocked to reclaim the struct. Listing the
+/// same field twice is a borrow error.
+///
+/// ```
+/// # use owning_key::{lock_fields, ForgettingKey, LockedBox};
+/// struct Pair {
+///     a: i32,
+///     b: String,
+/// }
+///
+/// let mut key = ForgettingKey::new();
+/// let mut owner: LockedBox<Pair> = key.lock(Box::new(Pair { a: 1, b: "2".into() }));
+/// let (a, b) = lock_fields!(key, owner => { a, b });
+/// *a.get_mut(&mut key) += 1;
+/// assert_eq!(b.get(&key), "2");
+///
+/// let pair = key.unlock(owner);
+/// assert_eq!(pair.a, 2);
+/// ```
+#[macro_export]
+macro_rules! lock_fields {
+    ($key:expr, $owner:expr => { $($field:ident),* $(,)? }) => {{
+        let key = &mut $key;
+        let value = key.unlock($crate::LockedBox::as_locked_mut(&mut $owner));
+        let key = &*key;
+        ($(key.lock::<$crate::LockedMut<'_, _>>(&mut value.$field),)*)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Access, ForgettingKey};
+    use alloc::{borrow::ToOwned, format, string::ToString, vec};
+    use core::{cell::Cell, marker::PhantomPinned};
+
+    /// Counts how many times it has been dropped.
+    #[derive(Debug)]
+    struct Counted<'a>(&'a Cell<usize>);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn box_round_trip() {
+        let mut key = ForgettingKey::new();
+        let b: LockedBox<i32> = key.lock(Box::new(1));
+        *b.get_mut(&mut key) += 1;
+        assert_eq!(*b.get(&key), 2);
+        assert_eq!(*key.unlock(b), 2);
+    }
+
+    #[test]
+    fn box_zst() {
+        let mut key = ForgettingKey::new();
+        let b: LockedBox<()> = key.lock(Box::new(()));
+        *b.get_mut(&mut key) = ();
+        assert_eq!(*b.get(&key), ());
+        assert_eq!(*key.unlock(b), ());
+    }
+
+    #[test]
+    fn box_unsized() {
+        let mut key = ForgettingKey::new();
+        let s: LockedBox<str> = key.lock(Box::from("abc"));
+        s.get_mut(&mut key).make_ascii_uppercase();
+        assert_eq!(s.get(&key), "ABC");
+        assert_eq!(&*key.unlock(s), "ABC");
+
+        let b: LockedBox<[u8]> = key.lock(Box::from([1, 2, 3]));
+        b.get_mut(&mut key)[0] = 4;
+        assert_eq!(b.get(&key), [4, 2, 3]);
+        assert_eq!(&*key.unlock(b), [4, 2, 3]);
+    }
+
+    #[test]
+    fn box_drops_contents_once() {
+        let drops = Cell::new(0);
+        let mut key = ForgettingKey::new();
+        let b: LockedBox<Counted<'_>> = key.lock(Box::new(Counted(&drops)));
+        let _ = b.get_mut(&mut key);
+        assert_eq!(drops.get(), 0);
+        drop(key.unlock(b));
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn vec_round_trip() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(Vec::with_capacity(4));
+        v.push(&mut key, 1);
+        v.push(&mut key, 2);
+        v.get_mut(&mut key)[0] = 3;
+        assert_eq!(v.get(&key), [3, 2]);
+        assert_eq!(v.capacity(), 4);
+        let vec = key.unlock(v);
+        assert_eq!(vec, [3, 2]);
+        assert_eq!(vec.capacity(), 4);
+    }
+
+    #[test]
+    fn vec_zst() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<()> = key.lock(vec![(); 3]);
+        v.push(&mut key, ());
+        assert_eq!(v.pop(&mut key), Some(()));
+        assert_eq!(v.get(&key).len(), 3);
+        assert_eq!(key.unlock(v).len(), 3);
+    }
+
+    #[test]
+    fn vec_drops_elements_once() {
+        let drops = Cell::new(0);
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<Counted<'_>> = key.lock(vec![Counted(&drops), Counted(&drops)]);
+        v.push(&mut key, Counted(&drops));
+        drop(v.pop(&mut key));
+        assert_eq!(drops.get(), 1);
+        drop(key.unlock(v));
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[test]
+    fn string_round_trip() {
+        let mut key = ForgettingKey::new();
+        let mut s: LockedString = key.lock("ab".to_owned());
+        s.push_str(&mut key, "cd");
+        s.get_mut(&mut key).make_ascii_uppercase();
+        assert_eq!(s.get(&key), "ABCD");
+        assert_eq!(key.unlock(s), "ABCD");
+    }
+
+    #[test]
+    fn cstring_round_trip() {
+        let mut key = ForgettingKey::new();
+        let s: LockedCString = key.lock(CString::new("abc").unwrap());
+        assert_eq!(s.get(&key).to_bytes(), b"abc");
+        assert_eq!(key.unlock(s).as_bytes(), b"abc");
+    }
+
+    #[test]
+    fn vec_deque_round_trip() {
+        let mut key = ForgettingKey::new();
+        let d: LockedVecDeque<i32> = key.lock(VecDeque::from([1, 2]));
+        d.make_contiguous(&mut key)[0] = 3;
+        assert_eq!(key.unlock(d), [3, 2]);
+    }
+
+    #[test]
+    fn vec_deque_push_pop() {
+        let mut key = ForgettingKey::new();
+        let mut d: LockedVecDeque<String> = key.lock(VecDeque::new());
+        d.push_back(&mut key, "b".to_owned());
+        d.push_front(&mut key, "a".to_owned());
+        d.push_back(&mut key, "c".to_owned());
+        let (front, back) = d.get(&key);
+        assert_eq!([front, back].concat(), ["a", "b", "c"]);
+        assert_eq!(d.pop_front(&mut key).as_deref(), Some("a"));
+        assert_eq!(d.pop_back(&mut key).as_deref(), Some("c"));
+        assert_eq!(key.unlock(d), ["b"]);
+    }
+
+    #[test]
+    fn vec_deque_send_sync() {
+        assert_send_sync::<LockedVecDeque<String>>();
+    }
+
+    #[test]
+    fn btree_map_send_sync() {
+        assert_send_sync::<LockedBTreeMap<String, String>>();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hash_map_send_sync() {
+        assert_send_sync::<LockedHashMap<String, String>>();
+    }
+
+    #[test]
+    fn btree_map_round_trip() {
+        let mut key = ForgettingKey::new();
+        let mut m: LockedBTreeMap<i32, i32> = key.lock(BTreeMap::new());
+        assert_eq!(m.insert(&mut key, 1, 2), None);
+        *m.get_mut(&mut key).get_mut(&1).unwrap() += 1;
+        assert_eq!(m.get(&key)[&1], 3);
+        assert_eq!(key.unlock(m), BTreeMap::from([(1, 3)]));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hash_map_round_trip() {
+        let mut key = ForgettingKey::new();
+        let mut m: LockedHashMap<i32, i32> = key.lock(HashMap::new());
+        assert_eq!(m.insert(&mut key, 1, 2), None);
+        assert!(m.contains_key(&key, &1));
+        assert_eq!(m.remove(&mut key, &1), Some(2));
+        assert!(key.unlock(m).is_empty());
+    }
+
+    #[test]
+    fn rc_refcounts() {
+        let drops = Cell::new(0);
+        let mut key = ForgettingKey::new();
+        let r: LockedRc<Counted<'_>> = key.lock(Rc::new(Counted(&drops)));
+        let clone = r.clone(&key);
+        assert_eq!(Rc::strong_count(&clone), 2);
+        assert!(r.get_mut(&mut key).is_none());
+        drop(clone);
+        assert!(r.get_mut(&mut key).is_some());
+        let weak = r.downgrade(&key);
+        assert_eq!((weak.strong_count(), weak.weak_count()), (1, 1));
+        assert!(r.get_mut(&mut key).is_none());
+        let rc = key.unlock(r);
+        assert_eq!(Rc::strong_count(&rc), 1);
+        drop(rc);
+        assert_eq!(drops.get(), 1);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn rc_unsized() {
+        let mut key = ForgettingKey::new();
+        let r: LockedRc<[i32]> = key.lock(Rc::from([1, 2]));
+        let clone = r.clone(&key);
+        assert_eq!(r.get(&key), [1, 2]);
+        assert_eq!(Rc::strong_count(&clone), 2);
+        drop(key.unlock(r));
+        assert_eq!(Rc::strong_count(&clone), 1);
+    }
+
+    #[test]
+    fn arc_refcounts() {
+        let mut key = ForgettingKey::new();
+        let a: LockedArc<i32> = key.lock(Arc::new(1));
+        let clone = a.clone(&key);
+        assert_eq!(Arc::strong_count(&clone), 2);
+        assert!(a.get_mut(&mut key).is_none());
+        drop(clone);
+        *a.get_mut(&mut key).unwrap() = 2;
+        assert_eq!(*a.get(&key), 2);
+        let weak = a.downgrade(&key);
+        assert_eq!((weak.strong_count(), weak.weak_count()), (1, 1));
+        drop(weak);
+        assert_eq!(Arc::try_unwrap(key.unlock(a)), Ok(2));
+    }
+
+    #[test]
+    fn weak_round_trip() {
+        let mut key = ForgettingKey::new();
+        let rc = Rc::new(1);
+        let w: LockedWeak<i32> = key.lock(Rc::downgrade(&rc));
+        assert_eq!(w.upgrade(&key).as_deref(), Some(&1));
+        drop(rc);
+        assert!(w.upgrade(&key).is_none());
+        drop(key.unlock(w));
+
+        let w: LockedWeakArc<i32> = key.lock(sync::Weak::new());
+        assert!(w.upgrade(&key).is_none());
+        drop(key.unlock(w));
+    }
+
+    /// Asserts that `f` panics because it accessed a value with the wrong key.
+    #[cfg(feature = "std")]
+    fn assert_wrong_key<R>(f: impl FnOnce() -> R) {
+        let err = crate::locked::catch_wrong_key(f);
+        assert_ne!(err.expected, err.got);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn unsafe_accessors_check_key() {
+        let mut key = ForgettingKey::new();
+        let mut other = ForgettingKey::new();
+
+        let s: LockedString = key.lock("abc".to_owned());
+        assert_wrong_key(|| unsafe { s.get_bytes_mut(&mut other) });
+        assert_wrong_key(|| unsafe { s.get_bytes_buf_mut(&mut other) });
+        assert_eq!(unsafe { s.get_bytes_mut(&mut key) }, b"abc");
+        assert_eq!(unsafe { s.get_bytes_buf_mut(&mut key) }.len(), s.capacity());
+        drop(key.unlock(s));
+
+        let mut v: LockedVec<i32> = key.lock(Vec::with_capacity(1));
+        assert_wrong_key(|| unsafe { v.set_len(&mut other, 0) });
+        v.get_buf_mut(&mut key)[0].write(1);
+        unsafe { v.set_len(&mut key, 1) };
+        assert_eq!(key.unlock(v), [1]);
+
+        // An empty slice has no allocation to leak when the panic consumes it.
+        let b: LockedBox<[MaybeUninit<i32>]> = key.lock(Box::new_uninit_slice(0));
+        assert_wrong_key(|| unsafe { b.assume_init(&mut other) });
+        let b: LockedBox<[MaybeUninit<i32>]> = key.lock(Box::new_uninit_slice(1));
+        b.get_mut(&mut key)[0].write(2);
+        let b = unsafe { b.assume_init(&mut key) };
+        assert_eq!(&*key.unlock(b), [2]);
+    }
+
+    #[test]
+    fn dedup_drain() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(vec![1, 1, 2, 3, 3, 3, 1, 4, 4]);
+        assert_eq!(v.dedup_drain(&mut key), [1, 3, 3, 4]);
+        assert_eq!(v.get(&key), [1, 2, 3, 1, 4]);
+        assert!(v.dedup_drain(&mut key).is_empty());
+        drop(key.unlock(v));
+
+        let mut v: LockedVec<i32> = key.lock(vec![5]);
+        assert!(v.dedup_drain(&mut key).is_empty());
+        assert_eq!(key.unlock(v), [5]);
+    }
+
+    #[test]
+    fn dedup_drain_drops_once() {
+        /// Compares equal if the ids are equal.
+        struct Item<'a> {
+            id: i32,
+            _counted: Counted<'a>,
+        }
+
+        impl PartialEq for Item<'_> {
+            fn eq(&self, other: &Self) -> bool {
+                self.id == other.id
+            }
+        }
+
+        let drops = Cell::new(0);
+        let mut key = ForgettingKey::new();
+        let items = [1, 1, 1, 2, 2, 3].map(|id| Item {
+            id,
+            _counted: Counted(&drops),
+        });
+        let mut v: LockedVec<Item<'_>> = key.lock(Vec::from(items));
+        let removed = v.dedup_drain(&mut key);
+        assert_eq!(drops.get(), 0);
+        assert_eq!(
+            removed.iter().map(|item| item.id).collect::<Vec<_>>(),
+            [1, 1, 2]
+        );
+        drop(removed);
+        assert_eq!(drops.get(), 3);
+        drop(key.unlock(v));
+        assert_eq!(drops.get(), 6);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn dedup_drain_panic_leaves_vec_valid() {
+        /// Panics when compared with an element equal to 3.
+        struct Item<'a> {
+            id: i32,
+            _counted: Counted<'a>,
+        }
+
+        impl PartialEq for Item<'_> {
+            fn eq(&self, other: &Self) -> bool {
+                assert!(self.id != 3 && other.id != 3);
+                self.id == other.id
+            }
+        }
+
+        let drops = Cell::new(0);
+        let mut key = ForgettingKey::new();
+        let items = [1, 1, 2, 2, 3, 4, 4].map(|id| Item {
+            id,
+            _counted: Counted(&drops),
+        });
+        let mut v: LockedVec<Item<'_>> = key.lock(Vec::from(items));
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| v.dedup_drain(&mut key)));
+        assert!(result.is_err());
+        // The removed duplicates were dropped during unwinding, and the rest
+        // of the elements, including the unvisited tail, remain in order.
+        assert_eq!(drops.get(), 2);
+        let ids: Vec<_> = v.get(&key).iter().map(|item| item.id).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 4]);
+        drop(key.unlock(v));
+        assert_eq!(drops.get(), 7);
+    }
+
+    #[test]
+    fn drain_partial_then_drop() {
+        let drops = Cell::new(0);
+        let mut key = ForgettingKey::new();
+        let items = (0..6).map(|_| Counted(&drops)).collect();
+        let mut v: LockedVec<Counted<'_>> = key.lock(items);
+        let ptr = v.get(&key).as_ptr();
+        let mut drain = v.drain(&mut key, 1..4);
+        assert_eq!(drain.len(), 3);
+        drop(drain.next());
+        assert_eq!(drain.as_slice().len(), 2);
+        drop(drain);
+        assert_eq!(drops.get(), 3);
+        assert_eq!(v.len(), 3);
+        // The tail was shifted back to directly follow the head.
+        assert!(ptr::eq(&v.get(&key)[1], unsafe { ptr.add(1) }));
+        drop(key.unlock(v));
+        assert_eq!(drops.get(), 6);
+    }
+
+    #[test]
+    fn drain_shifts_tail() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock((0..6).collect());
+        let mut drain = v.drain(&mut key, 1..=3);
+        assert_eq!(drain.next(), Some(1));
+        assert_eq!(drain.next_back(), Some(3));
+        assert_eq!(drain.as_slice(), [2]);
+        drop(drain);
+        assert_eq!(v.get(&key), [0, 4, 5]);
+        drop(key.unlock(v));
+    }
+
+    #[test]
+    fn drain_next_back() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(vec![1, 2, 3]);
+        let drained: Vec<_> = v.drain(&mut key, ..).rev().collect();
+        assert_eq!(drained, [3, 2, 1]);
+        assert!(v.is_empty());
+        drop(key.unlock(v));
+    }
+
+    #[test]
+    fn drain_empty_range() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(vec![1, 2, 3]);
+        let mut drain = v.drain(&mut key, 2..2);
+        assert_eq!(drain.next(), None);
+        assert_eq!(drain.next_back(), None);
+        drop(drain);
+        assert_eq!(key.unlock(v), [1, 2, 3]);
+    }
+
+    // These panic before the vector is modified, and the empty vector has no
+    // allocation to leak.
+    #[test]
+    #[should_panic(expected = "range end index 1 out of range for slice of length 0")]
+    fn drain_out_of_bounds() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(Vec::new());
+        v.drain(&mut key, 0..1);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice index starts at 1 but ends at 0")]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn drain_start_after_end() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(Vec::new());
+        v.drain(&mut key, 1..0);
+    }
+
+    #[test]
+    fn drain_forget_leaks_tail() {
+        let drops = Cell::new(0);
+        let mut key = ForgettingKey::new();
+        let items = (0..4).map(|_| Counted(&drops)).collect();
+        let mut v: LockedVec<Counted<'_>> = key.lock(items);
+        mem::forget(v.drain(&mut key, 1..2));
+        // As with `Vec::drain()`, the drained range and the tail are leaked,
+        // but the vector remains valid.
+        assert_eq!(v.len(), 1);
+        drop(key.unlock(v));
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn vec_map() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 2, 3]);
+        let mapped = v.map(&key, |x| x.to_string());
+        assert_eq!(mapped.key_id(), key.id());
+        assert_eq!(mapped.get(&key), ["1", "2", "3"]);
+        assert_eq!(key.unlock(v), [1, 2, 3]);
+        assert_eq!(key.unlock(mapped), ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn len_accessors() {
+        let mut key = ForgettingKey::new();
+        let b: LockedBox<[i32]> = key.lock(Box::from([1, 2]));
+        let r: LockedRc<[i32]> = key.lock(Rc::from([]));
+        let a: LockedArc<[i32]> = key.lock(Arc::from([1]));
+        let v: LockedVec<i32> = key.lock(vec![1, 2, 3]);
+        let s: LockedString = key.lock("ab".to_owned());
+        let c: LockedCString = key.lock(CString::new("abc").unwrap());
+        let empty: LockedCString = key.lock(CString::default());
+        assert_eq!((b.len(), b.is_empty()), (2, false));
+        assert_eq!((r.len(), r.is_empty()), (0, true));
+        assert_eq!((a.len(), a.is_empty()), (1, false));
+        assert_eq!((v.len(), v.is_empty()), (3, false));
+        assert_eq!((s.len(), s.is_empty()), (2, false));
+        assert_eq!((c.count_bytes(), c.is_empty()), (3, false));
+        assert_eq!((empty.count_bytes(), empty.is_empty()), (0, true));
+        drop((key.unlock(b), key.unlock(r), key.unlock(a), key.unlock(v)));
+        drop((key.unlock(s), key.unlock(c), key.unlock(empty)));
+    }
+
+    /// A type that must not move once pinned.
+    struct Pinned {
+        value: i32,
+        _pin: PhantomPinned,
+    }
+
+    impl Pinned {
+        fn new(value: i32) -> Self {
+            Self {
+                value,
+                _pin: PhantomPinned,
+            }
+        }
+    }
+
+    #[test]
+    fn pin_rc_keeps_pin() {
+        let mut key = ForgettingKey::new();
+        let r: LockedPinRc<Pinned> = key.lock(Rc::pin(Pinned::new(1)));
+        let pinned: Pin<&Pinned> = r.get(&key);
+        assert_eq!(pinned.value, 1);
+        let addr: *const Pinned = pinned.get_ref();
+        let clone: Pin<Rc<Pinned>> = r.clone(&key);
+        assert!(ptr::eq(clone.as_ref().get_ref(), addr));
+        drop(clone);
+        let r: Pin<Rc<Pinned>> = key.unlock(r);
+        assert!(ptr::eq(r.as_ref().get_ref(), addr));
+    }
+
+    #[test]
+    fn pin_arc_keeps_pin() {
+        let mut key = ForgettingKey::new();
+        let a: LockedPinArc<Pinned> = key.lock(Arc::pin(Pinned::new(1)));
+        let pinned: Pin<&Pinned> = a.get(&key);
+        assert_eq!(pinned.value, 1);
+        let addr: *const Pinned = pinned.get_ref();
+        let clone: Pin<Arc<Pinned>> = a.clone(&key);
+        assert!(ptr::eq(clone.as_ref().get_ref(), addr));
+        drop(clone);
+        let a: Pin<Arc<Pinned>> = key.unlock(a);
+        assert!(ptr::eq(a.as_ref().get_ref(), addr));
+    }
+
+    #[test]
+    fn lock_fields_reclaims_owner() {
+        struct Pair {
+            a: i32,
+            b: String,
+        }
+
+        let mut key = ForgettingKey::new();
+        let mut owner: LockedBox<Pair> = key.lock(Box::new(Pair {
+            a: 1,
+            b: "2".to_owned(),
+        }));
+        let (a, b) = crate::lock_fields!(key, owner => { a, b });
+        *a.get_mut(&mut key) += 1;
+        b.get_mut(&mut key).push('3');
+        assert_eq!(a.key_id(), key.id());
+        let pair = key.unlock(owner);
+        assert_eq!((pair.a, &*pair.b), (2, "23"));
+    }
+
+    #[test]
+    fn for_each_window_mut() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 2, 3, 4]);
+        let mut windows = Vec::new();
+        v.for_each_window_mut(&mut key, |w: &mut [i32; 2]| {
+            windows.push(*w);
+            w[1] += w[0];
+        });
+        assert_eq!(windows, [[1, 2], [3, 3], [6, 4]]);
+        assert_eq!(v.get(&key), [1, 3, 6, 10]);
+        v.for_each_window_mut(&mut key, |_: &mut [i32; 5]| unreachable!());
+        assert_eq!(key.unlock(v), [1, 3, 6, 10]);
+    }
+
+    #[test]
+    #[should_panic(expected = "window size must be non-zero")]
+    fn for_each_window_mut_zero() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(Vec::new());
+        v.for_each_window_mut(&mut key, |_: &mut [i32; 0]| {});
+    }
+
+    #[test]
+    fn sort_by_cached_key() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<&str> = key.lock(vec!["ccc", "a", "bb"]);
+        let mut calls = 0;
+        v.sort_by_cached_key(&mut key, |s| {
+            calls += 1;
+            s.len()
+        });
+        assert_eq!(calls, 3);
+        assert_eq!(key.unlock(v), ["a", "bb", "ccc"]);
+    }
+
+    #[test]
+    fn split_first_last() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 2, 3]);
+        assert_eq!(v.split_first(&key), Some((&1, &[2, 3][..])));
+        assert_eq!(v.split_last(&key), Some((&3, &[1, 2][..])));
+        let (first, rest) = v.split_first_mut(&mut key).unwrap();
+        *first += rest[0];
+        let (last, rest) = v.split_last_mut(&mut key).unwrap();
+        *last += rest[0];
+        assert_eq!(key.unlock(v), [3, 2, 6]);
+
+        let empty: LockedVec<i32> = key.lock(Vec::new());
+        assert_eq!(empty.split_first(&key), None);
+        assert_eq!(empty.split_last_mut(&mut key), None);
+        drop(key.unlock(empty));
+    }
+
+    #[test]
+    fn extend_from_locked() {
+        let mut key = ForgettingKey::new();
+        let mut other_key = ForgettingKey::new();
+        let mut v: LockedVec<String> = key.lock(vec!["a".to_owned()]);
+        let other: LockedVec<String> = other_key.lock(vec!["b".to_owned(), "c".to_owned()]);
+        v.extend_from_locked(&mut key, &other, &other_key);
+        assert_eq!(key.unlock(v), ["a", "b", "c"]);
+        assert_eq!(other_key.unlock(other), ["b", "c"]);
+    }
+
+    #[test]
+    fn truncate_chars() {
+        let mut key = ForgettingKey::new();
+        let mut s: LockedString = key.lock("añb€".to_owned());
+        s.truncate_chars(&mut key, 5);
+        assert_eq!(s.get(&key), "añb€");
+        s.truncate_chars(&mut key, 2);
+        assert_eq!(s.get(&key), "añ");
+        s.truncate_chars(&mut key, 0);
+        assert_eq!(key.unlock(s), "");
+    }
+
+    #[test]
+    fn chunks_exact() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 2, 3, 4, 5]);
+        let chunks = v.chunks_exact(&key, 2);
+        assert_eq!(chunks.remainder(), [5]);
+        assert!(chunks.eq([[1, 2], [3, 4]]));
+        for chunk in v.chunks_exact_mut(&mut key, 2) {
+            chunk.swap(0, 1);
+        }
+        assert_eq!(key.unlock(v), [2, 1, 4, 3, 5]);
+    }
+
+    #[test]
+    fn retain_drop_while() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(vec![1, 2, 5, 1, 2]);
+        v.retain_while(&mut key, |&x| x < 3);
+        assert_eq!(v.get(&key), [1, 2]);
+        v.retain_while(&mut key, |_| true);
+        assert_eq!(key.unlock(v), [1, 2]);
+
+        let mut v: LockedVec<String> = key.lock(["a", "b", "cc", "d"].map(str::to_owned).into());
+        v.drop_while(&mut key, |s| s.len() == 1);
+        assert_eq!(v.get(&key), ["cc", "d"]);
+        v.drop_while(&mut key, |_| true);
+        assert_eq!(key.unlock(v), Vec::<String>::new());
+    }
+
+    #[test]
+    fn vec_push_pop_front() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<String> = key.lock(vec!["b".to_owned()]);
+        v.push_front(&mut key, "a".to_owned());
+        assert_eq!(v.get(&key), ["a", "b"]);
+        assert_eq!(v.pop_front(&mut key).as_deref(), Some("a"));
+        assert_eq!(v.pop_front(&mut key).as_deref(), Some("b"));
+        assert_eq!(v.pop_front(&mut key), None);
+        drop(key.unlock(v));
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<String> = key.lock(vec!["a".to_owned()]);
+        v.get_or_insert_with(&mut key, 0, || unreachable!())
+            .push('b');
+        v.get_or_insert_with(&mut key, 1, || "c".to_owned())
+            .push('d');
+        assert_eq!(key.unlock(v), ["ab", "cd"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn get_or_insert_with_out_of_bounds() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(Vec::new());
+        v.get_or_insert_with(&mut key, 1, || 0);
+    }
+
+    #[test]
+    fn truncate_into() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<String> = key.lock(["a", "b", "c"].map(str::to_owned).into());
+        let mut sink = vec!["z".to_owned()];
+        v.truncate_into(&mut key, 3, &mut sink);
+        assert_eq!(sink, ["z"]);
+        v.truncate_into(&mut key, 1, &mut sink);
+        assert_eq!(sink, ["z", "b", "c"]);
+        assert_eq!(key.unlock(v), ["a"]);
+    }
+
+    #[test]
+    fn as_flattened() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<[i32; 2]> = key.lock(vec![[1, 2], [3, 4]]);
+        assert_eq!(v.as_flattened(&key), [1, 2, 3, 4]);
+        v.as_flattened_mut(&mut key)[1] = 5;
+        assert_eq!(key.unlock(v), [[1, 5], [3, 4]]);
+    }
+
+    #[test]
+    fn max_min_sum() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![3, 1, 4, 1, 5]);
+        assert_eq!(v.max(&key), Some(&5));
+        assert_eq!(v.min(&key), Some(&1));
+        let sum: i32 = v.sum(&key);
+        assert_eq!(sum, 14);
+        drop(key.unlock(v));
+
+        let empty: LockedVec<i32> = key.lock(Vec::new());
+        assert_eq!(empty.max(&key), None);
+        assert_eq!(empty.min(&key), None);
+        let sum: i32 = empty.sum(&key);
+        assert_eq!(sum, 0);
+        drop(key.unlock(empty));
+    }
+
+    #[test]
+    fn get_str_range() {
+        let mut key = ForgettingKey::new();
+        let s: LockedString = key.lock("añb".to_owned());
+        assert_eq!(s.get_str_range(&key, ..), Some("añb"));
+        assert_eq!(s.get_str_range(&key, 1..3), Some("ñ"));
+        assert_eq!(s.get_str_range(&key, 3..), Some("b"));
+        assert_eq!(s.get_str_range(&key, 2..), None);
+        assert_eq!(s.get_str_range(&key, ..5), None);
+        drop(key.unlock(s));
+    }
+
+    #[test]
+    fn clone_from_locked() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<String> = key.lock(Vec::with_capacity(4));
+        let capacity = v.capacity();
+        v.clone_from_locked(&mut key, &["a".to_owned(), "b".to_owned()]);
+        assert_eq!(v.get(&key), ["a", "b"]);
+        v.clone_from_locked(&mut key, &["c".to_owned()]);
+        assert_eq!(v.get(&key), ["c"]);
+        assert_eq!(v.capacity(), capacity);
+        v.clone_from_locked(&mut key, &[]);
+        assert_eq!(key.unlock(v), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rchunks() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 2, 3, 4, 5]);
+        assert!(v.rchunks(&key, 2).eq([&[4, 5][..], &[2, 3], &[1]]));
+        for chunk in v.rchunks_mut(&mut key, 2) {
+            chunk[0] = 0;
+        }
+        assert_eq!(key.unlock(v), [0, 0, 3, 0, 5]);
+    }
+
+    #[test]
+    fn fill_from_iter() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<String> = key.lock(Vec::with_capacity(3));
+        v.push(&mut key, "a".to_owned());
+        let capacity = v.capacity();
+        let mut iter = ["b", "c", "d", "e"].into_iter().map(str::to_owned);
+        let appended = v.fill_from_iter(&mut key, &mut iter);
+        assert_eq!(appended, capacity - 1);
+        assert_eq!(v.len(), capacity);
+        assert_eq!(v.capacity(), capacity);
+        assert_eq!(iter.count(), 4 - appended);
+        assert_eq!(v.fill_from_iter(&mut key, ["f".to_owned()].into_iter()), 0);
+        drop(key.unlock(v));
+    }
+
+    #[test]
+    fn contains_sorted() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 3, 5]);
+        assert!(v.contains_sorted(&key, &3));
+        assert!(!v.contains_sorted(&key, &4));
+        assert!(!v.contains_sorted(&key, &6));
+        drop(key.unlock(v));
+    }
+
+    #[test]
+    fn chunk_by() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 1, 2, 3, 3, 3]);
+        assert!(v
+            .chunk_by(&key, |a, b| a == b)
+            .eq([&[1, 1][..], &[2], &[3, 3, 3]]));
+        assert!(v
+            .chunk_by(&key, |a, b| a <= b)
+            .eq([&[1, 1, 2, 3, 3, 3][..]]));
+        drop(key.unlock(v));
+    }
+
+    #[test]
+    fn sort_dedup() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<String> =
+            key.lock(["b", "a", "b", "c", "a"].map(str::to_owned).into());
+        v.sort_dedup(&mut key);
+        assert_eq!(key.unlock(v), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn partition() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(vec![1, 2, 3, 4, 5, 6]);
+        let split = v.partition(&mut key, |&x| x % 2 == 0);
+        assert_eq!(split, 3);
+        let (evens, odds) = v.get(&key).split_at(split);
+        assert!(evens.iter().all(|x| x % 2 == 0));
+        assert!(odds.iter().all(|x| x % 2 == 1));
+        let mut all = key.unlock(v);
+        all.sort();
+        assert_eq!(all, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn as_ptr_range() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 2, 3]);
+        let range = v.as_ptr_range(&key);
+        assert_eq!(range.start, v.get(&key).as_ptr());
+        assert_eq!(unsafe { range.end.offset_from(range.start) }, 3);
+        let range = v.as_mut_ptr_range(&mut key);
+        unsafe { *range.start = 4 };
+        assert_eq!(key.unlock(v), [4, 2, 3]);
+    }
+
+    #[test]
+    fn iter_rev_last_n() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 2, 3]);
+        assert!(v.iter_rev(&key).eq(&[3, 2, 1]));
+        assert_eq!(v.last_n(&key, 2), [2, 3]);
+        assert!(v.last_n(&key, 0).is_empty());
+        assert_eq!(v.last_n(&key, 5), [1, 2, 3]);
+        drop(key.unlock(v));
+    }
+
+    #[test]
+    fn run_lengths() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<char> = key.lock("aabccca".chars().collect());
+        assert_eq!(
+            v.run_lengths(&key),
+            [('a', 2), ('b', 1), ('c', 3), ('a', 1)]
+        );
+        drop(key.unlock(v));
+
+        let empty: LockedVec<char> = key.lock(Vec::new());
+        assert!(empty.run_lengths(&key).is_empty());
+        drop(key.unlock(empty));
+    }
+
+    #[test]
+    fn retain_collect() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<String> = key.lock(["a", "bb", "c", "dd"].map(str::to_owned).into());
+        let mut sink = vec!["z".to_owned()];
+        v.retain_collect(&mut key, |s| s.len() == 1, &mut sink);
+        assert_eq!(sink, ["z", "bb", "dd"]);
+        assert_eq!(key.unlock(v), ["a", "c"]);
+    }
+
+    #[test]
+    fn display() {
+        let mut key = ForgettingKey::new();
+        let id = key.id();
+        let mut v: LockedVec<u8> = key.lock(Vec::with_capacity(4));
+        v.fill_from_iter(&mut key, [1, 2].into_iter());
+        let s: LockedString = key.lock(String::with_capacity(3));
+        let b: LockedBox<i32> = key.lock(Box::new(1));
+        let r: LockedRc<i32> = key.lock(Rc::new(1));
+        let a: LockedArc<i32> = key.lock(Arc::new(1));
+        let cap = v.capacity();
+        assert_eq!(
+            format!("{v}"),
+            format!("LockedVec {{ key_id: {id}, len: 2, cap: {cap} }}")
+        );
+        let cap = s.capacity();
+        assert_eq!(
+            format!("{s}"),
+            format!("LockedString {{ key_id: {id}, len: 0, cap: {cap} }}")
+        );
+        assert_eq!(format!("{b}"), format!("LockedBox {{ key_id: {id} }}"));
+        assert_eq!(format!("{r}"), format!("LockedRc {{ key_id: {id} }}"));
+        assert_eq!(format!("{a}"), format!("LockedArc {{ key_id: {id} }}"));
+        drop((key.unlock(v), key.unlock(s), key.unlock(b)));
+        drop((key.unlock(r), key.unlock(a)));
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn try_cast_vec() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<[u32; 2]> = key.lock(vec![[1, 2], [3, 4]]);
+        let v: LockedVec<u32> = v.try_cast_vec(&mut key).unwrap();
+        assert_eq!(v.key_id(), key.id());
+        assert_eq!(v.get(&key), [1, 2, 3, 4]);
+        let v: LockedVec<i32> = v.try_cast_vec(&mut key).unwrap();
+        assert_eq!(key.unlock(v), [1, 2, 3, 4]);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn try_cast_vec_fails() {
+        let mut key = ForgettingKey::new();
+        // The length in bytes is not a multiple of the new size.
+        let v: LockedVec<u32> = key.lock(vec![1, 2, 3]);
+        let v = v.try_cast_vec::<[u32; 2], _>(&mut key).unwrap_err();
+        assert_eq!((v.len(), v.capacity()), (3, 3));
+        assert_eq!(v.get(&key), [1, 2, 3]);
+        assert_eq!(key.unlock(v), [1, 2, 3]);
+
+        // The alignment differs.
+        let v: LockedVec<u8> = key.lock(vec![1, 2, 3, 4]);
+        let v = v.try_cast_vec::<u32, _>(&mut key).unwrap_err();
+        assert_eq!(key.unlock(v), [1, 2, 3, 4]);
+    }
+
+    #[cfg(feature = "unicode-segmentation")]
+    #[test]
+    fn truncate_graphemes() {
+        const ACCENTED: &str = "e\u{301}";
+        const FAMILY: &str = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        let mut key = ForgettingKey::new();
+        let mut s: LockedString = key.lock([ACCENTED, FAMILY, "a"].concat());
+        s.truncate_graphemes(&mut key, 3);
+        assert_eq!(s.get(&key), [ACCENTED, FAMILY, "a"].concat());
+        s.truncate_graphemes(&mut key, 2);
+        assert_eq!(s.get(&key), [ACCENTED, FAMILY].concat());
+        s.truncate_graphemes(&mut key, 1);
+        assert_eq!(s.get(&key), ACCENTED);
+        s.truncate_graphemes(&mut key, 0);
+        assert_eq!(key.unlock(s), "");
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_retain() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Item<'a> {
+            id: usize,
+            drops: &'a AtomicUsize,
+        }
+
+        impl Drop for Item<'_> {
+            fn drop(&mut self) {
+                self.drops.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = AtomicUsize::new(0);
+        let keep = |item: &Item<'_>| !item.id.is_multiple_of(3);
+        let mut key = ForgettingKey::new();
+        let items = (0..100).map(|id| Item { id, drops: &drops }).collect();
+        let mut v: LockedVec<Item<'_>> = key.lock(items);
+        v.par_retain(&mut key, keep);
+        assert_eq!(drops.load(Ordering::Relaxed), 34);
+
+        let mut expected: Vec<usize> = (0..100).collect();
+        expected.retain(|id| !id.is_multiple_of(3));
+        let ids: Vec<usize> = v.get(&key).iter().map(|item| item.id).collect();
+        assert_eq!(ids, expected);
+        drop(key.unlock(v));
+        assert_eq!(drops.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn reinterpret_transparent() {
+        #[repr(transparent)]
+        #[derive(Debug, PartialEq)]
+        struct Meters(u32);
+
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<u32> = key.lock(vec![1, 2, 3]);
+        let v: LockedVec<Meters> = unsafe { v.reinterpret() };
+        v.get_mut(&mut key)[0].0 = 4;
+        assert_eq!(v.get(&key), [Meters(4), Meters(2), Meters(3)]);
+        let v: LockedVec<u32> = unsafe { v.reinterpret() };
+        assert_eq!(v.key_id(), key.id());
+        assert_eq!(key.unlock(v), [4, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "size mismatch")]
+    fn reinterpret_size_mismatch() {
+        // An empty vector has no allocation to leak after the panic.
+        let v: LockedVec<u16> = ForgettingKey::new().lock(Vec::new());
+        let _: LockedVec<u32> = unsafe { v.reinterpret() };
+    }
+
+    #[test]
+    fn grow_uninit() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<String> = key.lock(vec!["a".to_owned()]);
+        let slots = v.grow_uninit(&mut key, 2);
+        assert_eq!(slots.len(), 2);
+        slots[0].write("b".to_owned());
+        slots[1].write("c".to_owned());
+        assert!(v.capacity() >= 3);
+        unsafe { v.set_len(&mut key, 3) };
+        assert_eq!(v.get(&key), ["a", "b", "c"]);
+        assert_eq!(key.unlock(v), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn maybe_shrink() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedVec<i32> = key.lock(Vec::with_capacity(16));
+        v.push(&mut key, 1);
+        v.push(&mut key, 2);
+        let capacity = v.capacity();
+        assert!(capacity >= 16);
+        // With a capacity of 16, 2 / 16 is above 0.1 but below 0.25.
+        v.maybe_shrink(&mut key, 0.1);
+        assert_eq!(v.capacity(), capacity);
+        v.maybe_shrink(&mut key, 0.25);
+        assert!(v.capacity() < capacity);
+        assert_eq!(key.unlock(v), [1, 2]);
+
+        let mut v: LockedVec<i32> = key.lock(Vec::with_capacity(10));
+        v.fill_from_iter(&mut key, 0..9);
+        let capacity = v.capacity();
+        // With a capacity of 10, 9 / 10 is not below 0.9.
+        v.maybe_shrink(&mut key, 0.9);
+        assert_eq!(v.capacity(), capacity);
+        assert_eq!(key.unlock(v).len(), 9);
+    }
+
+    #[test]
+    fn push_locked_str() {
+        let mut key = ForgettingKey::new();
+        let mut other_key = ForgettingKey::new();
+        let mut s: LockedString = key.lock("foo".to_owned());
+        let other: LockedString = other_key.lock("bar".to_owned());
+        s.push_locked_str(&mut key, &other, &other_key);
+        assert_eq!(s.get(&key), "foobar");
+        assert_eq!(other.get(&other_key), "bar");
+        assert_eq!(key.unlock(s), "foobar");
+        assert_eq!(other_key.unlock(other), "bar");
+    }
+
+    #[test]
+    fn cstring_try_from_raw_parts() {
+        let mut key = ForgettingKey::new();
+        let s: LockedString = key.lock("abc\0".to_owned());
+        let (ptr, len, capacity, key_id) = s.into_raw_parts();
+        let c = unsafe { LockedCString::try_from_raw_parts(ptr, len, capacity, key_id) }.unwrap();
+        assert_eq!(c.get(&key).to_bytes(), b"abc");
+        assert_eq!(key.unlock(c).as_bytes(), b"abc");
+
+        for bytes in [&b"abc"[..], b"a\0bc\0"] {
+            let v: LockedVec<u8> = key.lock(bytes.to_vec());
+            let (ptr, len, capacity, key_id) = v.into_raw_parts();
+            let result = unsafe { LockedCString::try_from_raw_parts(ptr, len, capacity, key_id) };
+            assert!(result.is_err());
+            // The parts are still owned by the caller after a failure.
+            let v = unsafe { LockedVec::<u8>::from_raw_parts(ptr, len, capacity, key_id) };
+            assert_eq!(key.unlock(v), bytes);
+        }
+    }
+
+    #[test]
+    fn drain_to_vec() {
+        let mut key = ForgettingKey::new();
+        for (range, drained, remaining) in [
+            (0..2, &[0, 1][..], &[2, 3, 4][..]),
+            (1..4, &[1, 2, 3], &[0, 4]),
+            (3..5, &[3, 4], &[0, 1, 2]),
+        ] {
+            let mut v: LockedVec<i32> = key.lock(vec![0, 1, 2, 3, 4]);
+            assert_eq!(v.drain_to_vec(&mut key, range), drained);
+            assert_eq!(v.get(&key), remaining);
+            assert_eq!(key.unlock(v), remaining);
+        }
+    }
+
+    #[test]
+    fn get_cell() {
+        let mut key = ForgettingKey::new();
+        let b: LockedBox<UnsafeCell<i32>> = key.lock(Box::new(UnsafeCell::new(1)));
+        let ptr = b.get_cell(&key);
+        assert_eq!(b.get_cell(&key), ptr);
+        assert_eq!(unsafe { *ptr }, 1);
+        unsafe { *ptr = 2 };
+        assert_eq!(unsafe { *b.get_cell(&key) }, 2);
+        assert_eq!(*b.get_mut(&mut key).get_mut(), 2);
+        assert_eq!(key.unlock(b).into_inner(), 2);
+    }
+
+    #[test]
+    fn leak() {
+        let mut key = ForgettingKey::new();
+        let b: LockedBox<Vec<i32>> = key.lock(Box::new(vec![1]));
+        let leaked = b.leak(&mut key);
+        leaked.push(2);
+        assert_eq!(leaked, &[1, 2]);
+        // Free the allocation so that Miri does not report a leak.
+        drop(unsafe { Box::from_raw(leaked) });
+    }
+
+    #[test]
+    fn as_locked_mut_and_ref() {
+        let mut key = ForgettingKey::new();
+        let mut b: LockedBox<i32> = key.lock(Box::new(1));
+        let m = b.as_locked_mut();
+        assert_eq!(m.key_id(), key.id());
+        *m.get_mut(&mut key) += 1;
+        *key.unlock(m) += 1;
+        assert_eq!(*b.get(&key), 3);
+
+        let r = b.as_locked_ref();
+        assert_eq!(r.key_id(), key.id());
+        assert_eq!(*r.get(&key), 3);
+        assert_eq!(*key.unlock(r), 3);
+        assert_eq!(*key.unlock(b), 3);
+    }
+
+    #[test]
+    fn into_arc_slice() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<String> = key.lock(vec!["a".to_owned(), "b".to_owned()]);
+        let a = v.into_arc_slice(&mut key);
+        assert_eq!(a.key_id(), key.id());
+        let clone = a.clone(&key);
+        assert_eq!(*clone, ["a", "b"]);
+        assert_eq!(a.get(&key), ["a", "b"]);
+        drop(clone);
+        assert_eq!(*key.unlock(a), ["a", "b"]);
+    }
+
+    #[test]
+    fn access_generic() {
+        fn size<L: AccessLocked, K: ?Sized + Key>(value: &L, key: &K) -> usize {
+            mem::size_of_val(value.access(key))
+        }
+
+        fn update<L: AccessLocked, K: Access<L>>(
+            value: &mut L,
+            key: &mut K,
+            f: impl FnOnce(&mut L::AccessTarget),
+        ) {
+            f(key.access_mut(value));
+        }
+
+        let mut key = ForgettingKey::new();
+        let mut b: LockedBox<u32> = key.lock(Box::new(1));
+        let mut v: LockedVec<u16> = key.lock(vec![1, 2, 3]);
+        let mut s: LockedString = key.lock("abc".to_owned());
+        assert_eq!(size(&b, &key), 4);
+        assert_eq!(size(&v, &key), 6);
+        assert_eq!(size(&s, &key), 3);
+
+        update(&mut b, &mut key, |x| *x += 1);
+        update(&mut v, &mut key, |x| x.reverse());
+        update(&mut s, &mut key, |x| x.make_ascii_uppercase());
+        assert_eq!(*key.unlock(b), 2);
+        assert_eq!(key.unlock(v), [3, 2, 1]);
+        assert_eq!(key.unlock(s), "ABC");
+    }
+
+    #[test]
+    fn rc_arc_raw_parts_round_trip() {
+        let mut key = ForgettingKey::new();
+        let rc: LockedRc<String> = key.lock(Rc::new("a".to_owned()));
+        let (ptr, key_id) = rc.into_raw_parts();
+        assert_eq!(key_id, key.id());
+        let rc = unsafe { LockedRc::from_raw_parts(ptr, key_id) };
+        let clone = rc.clone(&key);
+        assert_eq!(rc.strong_count(&key), 2);
+        drop(clone);
+        assert_eq!(*key.unlock(rc), "a");
+
+        let arc: LockedArc<String> = key.lock(Arc::new("b".to_owned()));
+        let (ptr, key_id) = arc.into_raw_parts();
+        assert_eq!(key_id, key.id());
+        let arc = unsafe { LockedArc::from_raw_parts(ptr, key_id) };
+        let clone = arc.clone(&key);
+        assert_eq!(arc.strong_count(&key), 2);
+        drop(clone);
+        assert_eq!(*key.unlock(arc), "b");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn lock_inner() {
+        let mut key = ForgettingKey::new();
+        let a: LockedArc<Mutex<Vec<i32>>> = key.lock(Arc::new(Mutex::new(vec![1])));
+        let clone = a.clone(&key);
+        a.lock_inner(&key).push(2);
+        assert_eq!(*clone.lock().unwrap(), [1, 2]);
+        clone.lock().unwrap().push(3);
+        assert_eq!(*a.lock_inner(&key), [1, 2, 3]);
+        drop(clone);
+        assert_eq!(key.unlock(a).lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn remove_variants() {
+        let mut key = ForgettingKey::new();
+        for len in 0..4 {
+            let full: Vec<i32> = (0..len).collect();
+
+            let mut v: LockedVec<i32> = key.lock(full.clone());
+            assert_eq!(v.pop(&mut key), full.last().copied());
+            assert_eq!(v.get(&key), &full[..full.len().saturating_sub(1)]);
+            assert_eq!(key.unlock(v).len(), full.len().saturating_sub(1));
+
+            let mut v: LockedVec<i32> = key.lock(full.clone());
+            assert_eq!(v.pop_front(&mut key), full.first().copied());
+            assert_eq!(v.get(&key), full.get(1..).unwrap_or_default());
+            assert_eq!(key.unlock(v).len(), full.len().saturating_sub(1));
+
+            for index in 0..full.len() {
+                let mut expected = full.clone();
+                expected.remove(index);
+                let mut v: LockedVec<i32> = key.lock(full.clone());
+                assert_eq!(v.remove(&mut key, index), full[index]);
+                assert_eq!(key.unlock(v), expected);
+
+                let mut expected = full.clone();
+                let last = expected.pop().unwrap();
+                if index < expected.len() {
+                    expected[index] = last;
+                }
+                let mut v: LockedVec<i32> = key.lock(full.clone());
+                assert_eq!(v.swap_remove(&mut key, index), full[index]);
+                assert_eq!(key.unlock(v), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn try_into_boxed_array() {
+        let mut key = ForgettingKey::new();
+        let v: LockedVec<i32> = key.lock(vec![1, 2, 3]);
+        let b: LockedBox<[i32; 3]> = v.try_into_boxed_array(&mut key).unwrap();
+        assert_eq!(b.key_id(), key.id());
+        assert_eq!(*b.get(&key), [1, 2, 3]);
+        assert_eq!(*key.unlock(b), [1, 2, 3]);
+
+        let mut vec = Vec::with_capacity(8);
+        vec.extend([1, 2, 3]);
+        let v: LockedVec<i32> = key.lock(vec);
+        let Err(v) = v.try_into_boxed_array::<_, 2>(&mut key) else {
+            panic!("length should not match");
+        };
+        assert_eq!(v.capacity(), 8);
+        assert_eq!(key.unlock(v), [1, 2, 3]);
+    }
+}
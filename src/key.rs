@@ -1,24 +1,14 @@
 use crate::{Key, KeyId, Locked};
-use core::{
-    borrow::Borrow,
-    cell::RefCell,
-    fmt::{self, Debug, Formatter},
-    hash::{Hash, Hasher},
-    marker::PhantomData,
-    mem::{self, ManuallyDrop},
-    ops::{Deref, DerefMut},
-    ptr::NonNull,
-};
+use core::{marker::PhantomData, mem::ManuallyDrop, ops::Deref};
 
 #[cfg(feature = "alloc")]
-use alloc::boxed::Box;
-
-#[cfg(feature = "std")]
-use std::{collections::HashSet, sync::Mutex};
+use crate::{LockedArc, LockedRc, LockedVec};
+#[cfg(feature = "alloc")]
+use alloc::{rc::Rc, sync::Arc, vec::Vec};
 
 #[derive(Debug)]
 pub struct ForgettingKey {
-    id: KeyId,
+    pub(crate) id: KeyId,
 }
 
 unsafe impl Key for ForgettingKey {
@@ -28,6 +18,13 @@ unsafe impl Key for ForgettingKey {
     }
 }
 
+impl Default for ForgettingKey {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ForgettingKey {
     #[inline]
     pub fn new() -> Self {
@@ -43,223 +40,247 @@ impl ForgettingKey {
     pub fn unlock<T: Locked>(&mut self, value: T) -> T::Unlocked {
         unsafe { value.raw_unlock(self) }
     }
-}
-
-union DropperInner<T> {
-    value: ManuallyDrop<T>,
-    _pad: u8,
-}
 
-struct Dropper<'a> {
-    ptr: NonNull<()>,
-    unlock_drop: unsafe fn(NonNull<()>, &mut ForgettingKey),
-    _marker: PhantomData<&'a ()>,
-}
+    /// Copies `s` into a new [`Rc<str>`](Rc) and locks it.
+    #[cfg(feature = "alloc")]
+    #[inline]
+    pub fn lock_rc_str(&self, s: &str) -> LockedRc<str> {
+        self.lock(Rc::from(s))
+    }
 
-impl PartialEq for Dropper<'_> {
+    /// Copies `s` into a new [`Arc<str>`](Arc) and locks it.
+    #[cfg(feature = "alloc")]
     #[inline]
-    fn eq(&self, other: &Self) -> bool {
-        self.ptr.eq(&other.ptr)
+    pub fn lock_arc_str(&self, s: &str) -> LockedArc<str> {
+        self.lock(Arc::from(s))
     }
-}
 
-impl Eq for Dropper<'_> {}
+    /// Unlocks a vector and shrinks its capacity to fit its length.
+    #[cfg(feature = "alloc")]
+    #[inline]
+    pub fn unlock_shrunk<T>(&mut self, value: LockedVec<T>) -> Vec<T> {
+        let mut vec = self.unlock(value);
+        vec.shrink_to_fit();
+        vec
+    }
 
-impl Debug for Dropper<'_> {
     #[inline]
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Dropper")
-            .field("ptr", &self.ptr)
-            .field("unlock_drop", &(self.unlock_drop as *const ()))
-            .field("_marker", &self._marker)
-            .finish()
+    pub fn lock_guard<T: Locked>(&mut self, value: T::Unlocked) -> LockGuard<'_, T> {
+        let value = ManuallyDrop::new(self.lock(value));
+        LockGuard { value, key: self }
     }
 }
 
-impl Hash for Dropper<'_> {
+/// A key that always has the same fixed [`KeyId`], for programs and tests
+/// with a single logical owner. Like a [`ForgettingKey`], it does not track
+/// the values locked with it.
+///
+/// Since every `UnitKey` has the same [`KeyId`], any value locked with one
+/// `UnitKey` can be accessed with any other.
+#[derive(Debug)]
+pub struct UnitKey {
+    _private: (),
+}
+
+unsafe impl Key for UnitKey {
     #[inline]
-    fn hash<H>(&self, state: &mut H)
-    where
-        H: Hasher,
-    {
-        self.ptr.hash(state);
+    fn id(&self) -> KeyId {
+        KeyId::UNIT
     }
 }
 
-impl Borrow<NonNull<()>> for Dropper<'_> {
+impl UnitKey {
+    /// # Safety
+    ///
+    /// No other `UnitKey` may be live at any point during the lifetime of the
+    /// returned key.
     #[inline]
-    fn borrow(&self) -> &NonNull<()> {
-        &self.ptr
+    pub unsafe fn new() -> Self {
+        Self { _private: () }
     }
-}
 
-impl<'a> Dropper<'a> {
     #[inline]
-    fn new<T: Locked + 'a>(value: T) -> Self {
-        let value = ManuallyDrop::new(value);
-        let dropper = Box::new(DropperInner { value });
-        let ptr = NonNull::new(Box::into_raw(dropper)).unwrap();
-        Self {
-            ptr: ptr.cast(),
-            unlock_drop: |ptr, key| {
-                let ptr: NonNull<DropperInner<T>> = ptr.cast();
-                let mut dropper = unsafe { Box::from_raw(ptr.as_ptr()) };
-                let value = unsafe { ManuallyDrop::take(&mut dropper.value) };
-                drop(key.unlock(value));
-            },
-            _marker: PhantomData,
-        }
+    pub fn lock<T: Locked>(&self, value: T::Unlocked) -> T {
+        unsafe { T::raw_lock(value, self) }
     }
 
     #[inline]
-    fn unlock_drop(self, key: &mut ForgettingKey) {
-        unsafe { (self.unlock_drop)(self.ptr, key) };
+    pub fn unlock<T: Locked>(&mut self, value: T) -> T::Unlocked {
+        unsafe { value.raw_unlock(self) }
     }
 }
 
+/// A locked value that is unlocked and dropped when the guard is dropped.
 #[derive(Debug)]
-pub struct Dropping<T> {
-    value: T,
-    ptr: NonNull<()>,
+pub struct LockGuard<'k, T: Locked> {
+    value: ManuallyDrop<T>,
+    key: &'k mut ForgettingKey,
 }
 
-impl<T> Deref for Dropping<T> {
+impl<T: Locked> Deref for LockGuard<'_, T> {
     type Target = T;
 
+    #[inline]
     fn deref(&self) -> &Self::Target {
         &self.value
     }
 }
 
-impl<T> DerefMut for Dropping<T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.value
+impl<T: Locked> Drop for LockGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        let value = unsafe { ManuallyDrop::take(&mut self.value) };
+        drop(self.key.unlock(value));
     }
 }
 
-fn unlock_drop_all<'a, I: Iterator<Item = Dropper<'a>>>(droppers: I, key: &mut ForgettingKey) {
-    struct DropGuard<'a, 'b, I: Iterator<Item = Dropper<'a>>> {
-        droppers: I,
-        key: &'b mut ForgettingKey,
+impl<T: Locked> LockGuard<'_, T> {
+    #[inline]
+    pub fn key(&self) -> &ForgettingKey {
+        self.key
     }
-    impl<'a, I: Iterator<Item = Dropper<'a>>> Drop for DropGuard<'a, '_, I> {
-        fn drop(&mut self) {
-            for dropper in &mut self.droppers {
-                dropper.unlock_drop(self.key);
-            }
-        }
+
+    /// Returns the locked value along with a [`Proof`] for the borrowed key,
+    /// so that it can be accessed mutably.
+    ///
+    /// This does not expose the key itself, since swapping it out would make
+    /// the guard unlock the value with the wrong key when it is dropped.
+    #[inline]
+    pub fn split(&mut self) -> (&T, Proof<'_>) {
+        (&self.value, self.key.prove())
     }
-    let mut guard = DropGuard { droppers, key };
-    for dropper in &mut guard.droppers {
-        dropper.unlock_drop(guard.key);
+
+    #[inline]
+    pub fn unlock(self) -> T::Unlocked {
+        let mut this = ManuallyDrop::new(self);
+        let value = unsafe { ManuallyDrop::take(&mut this.value) };
+        this.key.unlock(value)
     }
-    mem::forget(guard);
 }
 
+/// A token that stands in for a mutably borrowed key, with the same
+/// [`KeyId`]. This lets a function require access to locked values without
+/// taking the key object itself.
+///
+/// This is created by [`Key::prove()`].
 #[derive(Debug)]
-pub struct LocalDroppingKey<'a> {
-    inner: ForgettingKey,
-    droppers: RefCell<HashSet<Dropper<'a>>>,
+pub struct Proof<'k> {
+    id: KeyId,
+    _marker: PhantomData<&'k mut ()>,
 }
 
-unsafe impl Key for LocalDroppingKey<'_> {
+unsafe impl Key for Proof<'_> {
     #[inline]
     fn id(&self) -> KeyId {
-        self.inner.id()
-    }
-}
-
-impl Drop for LocalDroppingKey<'_> {
-    #[inline]
-    fn drop(&mut self) {
-        let droppers = mem::take(self.droppers.get_mut()).into_iter();
-        unlock_drop_all(droppers, &mut self.inner);
+        self.id
     }
 }
 
-impl<'a> LocalDroppingKey<'a> {
+impl Proof<'_> {
     #[inline]
-    pub fn new() -> Self {
+    pub(crate) fn new(id: KeyId) -> Self {
         Self {
-            inner: ForgettingKey::new(),
-            droppers: RefCell::new(HashSet::new()),
+            id,
+            _marker: PhantomData,
         }
     }
+}
 
-    #[inline]
-    pub fn lock<T: Locked + 'a>(&self, value: T::Unlocked) -> Dropping<T> {
-        let value: T = self.inner.lock(value);
-        let dropper = Dropper::new(unsafe { value.raw_clone() });
-        let ptr = dropper.ptr;
-        if !self.droppers.borrow_mut().insert(dropper) {
-            unreachable!("box address should be unique");
-        }
-        Dropping { value, ptr }
-    }
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::LockedBox;
+    use alloc::{boxed::Box, vec::Vec};
+    use core::cell::Cell;
 
-    #[inline]
-    pub fn unlock<T: Locked + 'a>(&mut self, value: Dropping<T>) -> T::Unlocked {
-        let ptr = value.ptr;
-        let value = self.inner.unlock(value.value);
-        if !self.droppers.get_mut().remove(&ptr) {
-            unreachable!("value should correspond to dropper");
+    /// Counts how many times it has been dropped.
+    struct Counted<'a>(&'a Cell<usize>);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
         }
-        value
     }
-}
 
-#[derive(Debug)]
-pub struct DroppingKey<'a> {
-    inner: ForgettingKey,
-    droppers: Mutex<HashSet<Dropper<'a>>>,
-}
-
-unsafe impl Send for DroppingKey<'_> {}
-
-unsafe impl Sync for DroppingKey<'_> {}
+    #[test]
+    fn lock_guard_drops_value() {
+        let drops = Cell::new(0);
+        let mut key = ForgettingKey::new();
+        {
+            let mut guard =
+                key.lock_guard::<LockedBox<(Counted<'_>, i32)>>(Box::new((Counted(&drops), 1)));
+            let (value, mut proof) = guard.split();
+            value.get_mut(&mut proof).1 += 1;
+            assert_eq!(guard.get(guard.key()).1, 2);
+        }
+        assert_eq!(drops.get(), 1);
 
-unsafe impl Key for DroppingKey<'_> {
-    #[inline]
-    fn id(&self) -> KeyId {
-        self.inner.id()
+        // The key can be used again once the guard is gone.
+        let value: LockedBox<i32> = key.lock(Box::new(3));
+        assert_eq!(*key.unlock(value), 3);
     }
-}
 
-impl Drop for DroppingKey<'_> {
-    #[inline]
-    fn drop(&mut self) {
-        let droppers = mem::take(self.droppers.get_mut().unwrap()).into_iter();
-        unlock_drop_all(droppers, &mut self.inner);
+    #[test]
+    fn lock_guard_unlock() {
+        let drops = Cell::new(0);
+        let mut key = ForgettingKey::new();
+        let guard = key.lock_guard::<LockedBox<Counted<'_>>>(Box::new(Counted(&drops)));
+        let value = guard.unlock();
+        assert_eq!(drops.get(), 0);
+        drop(value);
+        assert_eq!(drops.get(), 1);
     }
-}
 
-impl<'a> DroppingKey<'a> {
-    #[inline]
-    pub fn new() -> Self {
-        Self {
-            inner: ForgettingKey::new(),
-            droppers: Mutex::new(HashSet::new()),
+    #[test]
+    fn proof_accesses_values() {
+        fn bump(value: &LockedBox<i32>, mut proof: Proof<'_>) {
+            *value.get_mut(&mut proof) += 1;
         }
+
+        let mut key = ForgettingKey::new();
+        let value: LockedBox<i32> = key.lock(Box::new(1));
+        let proof = key.prove();
+        assert_eq!(proof.id(), key.id());
+        bump(&value, key.prove());
+        assert_eq!(*value.get(&key.prove()), 2);
+        assert_eq!(*key.unlock(value), 2);
     }
 
-    #[inline]
-    pub fn lock<T: Locked + Send + Sync + 'a>(&self, value: T::Unlocked) -> Dropping<T> {
-        let value: T = self.inner.lock(value);
-        let dropper = Dropper::new(unsafe { value.raw_clone() });
-        let ptr = dropper.ptr;
-        if !self.droppers.lock().unwrap().insert(dropper) {
-            unreachable!("box address should be unique");
-        }
-        Dropping { value, ptr }
+    #[test]
+    fn same_as() {
+        let key = ForgettingKey::new();
+        let other = ForgettingKey::new();
+        let reborrow: &ForgettingKey = &key;
+        assert!(key.same_as(reborrow));
+        assert!(reborrow.same_as(&key));
+        assert!(key.same_as(&Proof::new(reborrow.id())));
+        assert!(!key.same_as(&other));
+        assert!(!other.same_as(reborrow));
     }
 
-    #[inline]
-    pub fn unlock<T: Locked + Send + Sync + 'a>(&mut self, value: Dropping<T>) -> T::Unlocked {
-        let ptr = value.ptr;
-        let value = self.inner.unlock(value.value);
-        if !self.droppers.get_mut().unwrap().remove(&ptr) {
-            unreachable!("value should correspond to dropper");
-        }
-        value
+    #[test]
+    fn unit_key() {
+        let value: LockedBox<i32> = {
+            let key = unsafe { UnitKey::new() };
+            assert_eq!(key.id(), KeyId::UNIT);
+            key.lock(Box::new(1))
+        };
+        assert_eq!(value.key_id(), KeyId::UNIT);
+
+        // A later `UnitKey` can access values locked by an earlier one.
+        let mut key = unsafe { UnitKey::new() };
+        *value.get_mut(&mut key) += 1;
+        assert_eq!(*key.unlock(value), 2);
+    }
+
+    #[test]
+    fn unlock_shrunk() {
+        let mut key = ForgettingKey::new();
+        let mut vec = Vec::with_capacity(16);
+        vec.extend([1, 2, 3]);
+        let v: LockedVec<i32> = key.lock(vec);
+        let vec = key.unlock_shrunk(v);
+        assert_eq!(vec, [1, 2, 3]);
+        assert_eq!(vec.capacity(), vec.len());
     }
 }
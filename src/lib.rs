@@ -1,7 +1,13 @@
 #![no_std]
 #![forbid(unsafe_op_in_unsafe_fn)]
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{
+    fmt::{self, Display, Formatter},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "std")]
+use core::cell::Cell;
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
@@ -9,25 +15,110 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+#[cfg(feature = "std")]
+mod checked;
+#[cfg(feature = "std")]
+mod dropping;
+#[cfg(feature = "alloc")]
+mod heap;
 mod key;
 mod locked;
+#[cfg(feature = "alloc")]
+mod registry;
+#[cfg(feature = "std")]
+pub use checked::*;
+#[cfg(feature = "std")]
+pub use dropping::*;
+#[cfg(feature = "alloc")]
+pub use heap::*;
 pub use key::*;
 pub use locked::*;
+#[cfg(feature = "alloc")]
+pub use registry::*;
+
+#[cfg(feature = "derive")]
+pub use owning_key_derive::Locked;
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct KeyId {
     id: usize,
 }
 
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of ids that each thread reserves from [`COUNTER`] at once.
+#[cfg(feature = "std")]
+const BLOCK_SIZE: usize = 256;
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// The range of ids reserved by the current thread that have not yet been
+    /// handed out.
+    static BLOCK: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+#[inline]
+fn reserve_ids(n: usize) -> Option<usize> {
+    COUNTER
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| id.checked_add(n))
+        .ok()
+}
+
 impl KeyId {
     #[inline]
     pub fn new() -> Self {
-        static COUNTER: AtomicUsize = AtomicUsize::new(0);
-        let id = COUNTER
-            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| id.checked_add(1))
-            .expect("unique counter for KeyId should not overflow");
+        #[cfg(feature = "std")]
+        if let Ok(Some(id)) = BLOCK.try_with(|block| {
+            let (mut next, mut end) = block.get();
+            if next == end {
+                next = reserve_ids(BLOCK_SIZE)?;
+                end = next + BLOCK_SIZE;
+            }
+            block.set((next + 1, end));
+            Some(next)
+        }) {
+            return Self { id };
+        }
+        let id = reserve_ids(1).expect("unique counter for KeyId should not overflow");
         Self { id }
     }
+
+    /// The [`KeyId`] of every [`UnitKey`]. This is never returned by
+    /// [`KeyId::new()`], since the counter stops before reaching it.
+    pub(crate) const UNIT: Self = Self { id: usize::MAX };
+
+    /// Resets the global counter, so that subsequent [`KeyId`]s are created
+    /// starting from 0 again. This is only intended for tests that depend on
+    /// the exact values of [`KeyId`]s.
+    ///
+    /// # Safety
+    ///
+    /// After this call, no [`KeyId`] created before the call may be used, and
+    /// no key or locked value holding such a [`KeyId`] may be accessed. Also,
+    /// no thread other than the current thread that has created a [`KeyId`]
+    /// before the call may create another one after the call.
+    #[cfg(feature = "testing")]
+    #[inline]
+    pub unsafe fn reset_counter_for_testing() {
+        #[cfg(feature = "std")]
+        let _ = BLOCK.try_with(|block| block.set((0, 0)));
+        COUNTER.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Formats the id as `#n`.
+impl Display for KeyId {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.id)
+    }
+}
+
+impl Default for KeyId {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// # Safety
@@ -50,6 +141,39 @@ impl KeyId {
 /// checker.
 pub unsafe trait Key {
     fn id(&self) -> KeyId;
+
+    /// Returns whether `other` has the same [`KeyId`] as this key, i.e.,
+    /// whether it is the same logical key.
+    #[inline]
+    fn same_as<K: ?Sized + Key>(&self, other: &K) -> bool
+    where
+        Self: Sized,
+    {
+        self.id() == other.id()
+    }
+
+    /// Returns a [`Proof`] that grants the same access as this key for as long
+    /// as it is borrowed.
+    #[inline]
+    fn prove(&mut self) -> Proof<'_> {
+        Proof::new(self.id())
+    }
+
+    /// Wraps this key in a [`CheckedKey`], which checks in debug builds that no
+    /// other live `CheckedKey` has the same [`KeyId`].
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if another live `CheckedKey` has the same
+    /// [`KeyId`] as this key.
+    #[cfg(feature = "std")]
+    #[inline]
+    fn debug_assert_unique(self) -> CheckedKey<Self>
+    where
+        Self: Sized,
+    {
+        CheckedKey::new(self)
+    }
 }
 
 pub trait Locked {
@@ -88,3 +212,76 @@ pub trait Locked {
     /// create this value explicitly permits this function to be called.
     unsafe fn raw_clone(&self) -> Self;
 }
+
+/// A locked value whose contents can be accessed given a key.
+pub trait AccessLocked: Locked {
+    /// The type of the contents, e.g., `[T]` for a
+    /// [`LockedVec<T>`](crate::LockedVec).
+    type AccessTarget: ?Sized;
+
+    fn access<'k, K: ?Sized + Key>(&'k self, key: &'k K) -> &'k Self::AccessTarget;
+
+    fn access_mut<'k, K: ?Sized + Key>(&'k mut self, key: &'k mut K) -> &'k mut Self::AccessTarget;
+}
+
+/// A key that can access the contents of a locked value of type `L`. This is
+/// implemented for every [`Key`], so that code can be generic over both the
+/// key and the locked type.
+pub trait Access<L: AccessLocked> {
+    fn access<'k>(&'k self, value: &'k L) -> &'k L::AccessTarget;
+
+    fn access_mut<'k>(&'k mut self, value: &'k mut L) -> &'k mut L::AccessTarget;
+}
+
+impl<K: ?Sized + Key, L: AccessLocked> Access<L> for K {
+    #[inline]
+    fn access<'k>(&'k self, value: &'k L) -> &'k L::AccessTarget {
+        value.access(self)
+    }
+
+    #[inline]
+    fn access_mut<'k>(&'k mut self, value: &'k mut L) -> &'k mut L::AccessTarget {
+        value.access_mut(self)
+    }
+}
+
+/// A locked value whose contents can be serialized given a shared key.
+#[cfg(feature = "serde")]
+pub trait SerializeLocked: Locked {
+    fn serialize_locked<K: ?Sized + Key, S: serde::Serializer>(
+        &self,
+        key: &K,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>;
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::{collections::HashSet, format, thread, vec::Vec};
+
+    #[test]
+    fn key_id_display() {
+        assert_eq!(format!("{}", KeyId { id: 3 }), "#3");
+        let id = KeyId::new();
+        assert_eq!(format!("{id}"), format!("#{}", id.id));
+    }
+
+    #[test]
+    fn key_ids_unique_across_threads() {
+        // Enough ids per thread to cross a block boundary.
+        const PER_THREAD: usize = BLOCK_SIZE + 1;
+        let ids: Vec<Vec<KeyId>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| s.spawn(|| (0..PER_THREAD).map(|_| KeyId::new()).collect()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let mut seen = HashSet::new();
+        for id in ids.into_iter().flatten() {
+            assert_ne!(id, KeyId::UNIT);
+            assert!(seen.insert(id.id), "duplicate KeyId: {id}");
+        }
+        assert_eq!(seen.len(), 4 * PER_THREAD);
+    }
+}
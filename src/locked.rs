@@ -1,34 +1,89 @@
-use crate::{Key, KeyId, Locked};
+use crate::{AccessLocked, Key, KeyId, Locked};
 use core::{
-    ffi::CStr,
+    fmt::{self, Debug, Display, Formatter},
     marker::PhantomData,
     mem::{ManuallyDrop, MaybeUninit},
-    ptr::NonNull,
-    slice, str,
+    pin::Pin,
+    ptr::{self, NonNull},
+    slice,
 };
 
-#[cfg(feature = "alloc")]
-use alloc::{
-    boxed::Box,
-    ffi::CString,
-    rc::{self, Rc},
-    string::String,
-    sync::{self, Arc},
-    vec::Vec,
-};
+#[cfg(feature = "serde")]
+use crate::SerializeLocked;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+#[inline]
+pub(crate) fn check_id(key_id: KeyId, value_id: KeyId) {
+    if let Err(err) = try_check_id(key_id, value_id) {
+        wrong_key(err);
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn wrong_key(err: WrongKeyError) -> ! {
+    #[cfg(feature = "std")]
+    std::panic::panic_any(err);
+    #[cfg(not(feature = "std"))]
+    panic!("{err}");
+}
+
+/// Runs `f`, which must panic because it accessed a value with the wrong key,
+/// and returns the [`WrongKeyError`] payload.
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn catch_wrong_key<R>(f: impl FnOnce() -> R) -> WrongKeyError {
+    let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) else {
+        panic!("expected a panic");
+    };
+    *payload.downcast::<WrongKeyError>().unwrap()
+}
 
 #[inline]
-fn check_id(key_id: KeyId, value_id: KeyId) {
-    if key_id != value_id {
-        panic!("locked value accessed with wrong key: expected {value_id:?}, got {key_id:?}");
+pub(crate) fn try_check_id(key_id: KeyId, value_id: KeyId) -> Result<(), WrongKeyError> {
+    if key_id == value_id {
+        Ok(())
+    } else {
+        Err(WrongKeyError {
+            expected: value_id,
+            got: key_id,
+        })
+    }
+}
+
+/// The error returned when a locked value is accessed with a key other than
+/// the one used to create it.
+///
+/// With the `std` feature, this is also the payload of the panic when a
+/// locked value is accessed with the wrong key, so it can be recovered with
+/// [`std::panic::catch_unwind()`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WrongKeyError {
+    /// The [`KeyId`] of the locked value.
+    pub expected: KeyId,
+    /// The [`KeyId`] of the key that was used.
+    pub got: KeyId,
+}
+
+impl Display for WrongKeyError {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "locked value accessed with wrong key: expected {:?}, got {:?}",
+            self.expected, self.got,
+        )
     }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for WrongKeyError {}
+
 #[derive(Debug)]
 pub struct LockedMut<'a, T: ?Sized> {
-    ptr: NonNull<T>,
-    key_id: KeyId,
-    _marker: PhantomData<&'a mut T>,
+    pub(crate) ptr: NonNull<T>,
+    pub(crate) key_id: KeyId,
+    pub(crate) _marker: PhantomData<&'a mut T>,
 }
 
 impl<'a, T: ?Sized> Locked for LockedMut<'a, T> {
@@ -80,11 +135,64 @@ impl<'a, T: ?Sized> LockedMut<'a, T> {
         unsafe { &mut *self.ptr.as_ptr() }
     }
 
+    #[inline]
+    pub fn try_get<'k, K: ?Sized + Key>(&self, key: &'k K) -> Result<&'k T, WrongKeyError>
+    where
+        'a: 'k,
+    {
+        try_check_id(key.id(), self.key_id)?;
+        unsafe { Ok(self.ptr.as_ref()) }
+    }
+
+    #[inline]
+    pub fn try_get_mut<'k, K: ?Sized + Key>(
+        &self,
+        key: &'k mut K,
+    ) -> Result<&'k mut T, WrongKeyError>
+    where
+        'a: 'k,
+    {
+        try_check_id(key.id(), self.key_id)?;
+        unsafe { Ok(&mut *self.ptr.as_ptr()) }
+    }
+
+    /// # Safety
+    ///
+    /// `key.id()` must be equal to `self.key_id()`. Otherwise, the returned
+    /// reference may alias a reference obtained through the correct key,
+    /// which is undefined behavior.
+    #[inline]
+    pub unsafe fn get_unchecked<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T
+    where
+        'a: 'k,
+    {
+        let _ = key;
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// # Safety
+    ///
+    /// `key.id()` must be equal to `self.key_id()`. Otherwise, the returned
+    /// reference may alias a reference obtained through the correct key,
+    /// which is undefined behavior.
+    #[inline]
+    pub unsafe fn get_unchecked_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut T
+    where
+        'a: 'k,
+    {
+        let _ = key;
+        unsafe { &mut *self.ptr.as_ptr() }
+    }
+
     #[inline]
     pub fn into_raw_parts(self) -> (NonNull<T>, KeyId) {
         (self.ptr, self.key_id)
     }
 
+    /// # Safety
+    ///
+    /// `ptr` and `key_id` must have been obtained from a previous call to
+    /// [`LockedMut::into_raw_parts()`] with the same lifetime `'a`.
     #[inline]
     pub unsafe fn from_raw_parts(ptr: NonNull<T>, key_id: KeyId) -> Self {
         Self {
@@ -95,14 +203,29 @@ impl<'a, T: ?Sized> LockedMut<'a, T> {
     }
 }
 
+impl<T> LockedMut<'_, [T]> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ptr.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ptr.len() == 0
+    }
+}
+
+/// A locked shared reference. This never yields a `&mut T`, even though
+/// unlocking it requires a mutable key.
 #[derive(Debug)]
-pub struct LockedBox<T: ?Sized> {
-    ptr: NonNull<T>,
-    key_id: KeyId,
+pub struct LockedRef<'a, T: ?Sized> {
+    pub(crate) ptr: NonNull<T>,
+    pub(crate) key_id: KeyId,
+    pub(crate) _marker: PhantomData<&'a T>,
 }
 
-impl<T: ?Sized> Locked for LockedBox<T> {
-    type Unlocked = Box<T>;
+impl<'a, T: ?Sized> Locked for LockedRef<'a, T> {
+    type Unlocked = &'a T;
 
     #[inline]
     fn key_id(&self) -> KeyId {
@@ -110,16 +233,19 @@ impl<T: ?Sized> Locked for LockedBox<T> {
     }
 
     #[inline]
-    unsafe fn raw_lock<K: ?Sized + Key>(b: Self::Unlocked, key: &K) -> Self {
+    unsafe fn raw_lock<K: ?Sized + Key>(r: Self::Unlocked, key: &K) -> Self {
         let key_id = key.id();
-        let ptr = NonNull::new(Box::into_raw(b)).unwrap();
-        Self { ptr, key_id }
+        Self {
+            ptr: r.into(),
+            key_id,
+            _marker: PhantomData,
+        }
     }
 
     #[inline]
     unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
         check_id(key.id(), self.key_id);
-        unsafe { Box::from_raw(self.ptr.as_ptr()) }
+        unsafe { self.ptr.as_ref() }
     }
 
     #[inline]
@@ -128,288 +254,394 @@ impl<T: ?Sized> Locked for LockedBox<T> {
     }
 }
 
-impl<T: ?Sized> LockedBox<T> {
+impl<'a, T: ?Sized> LockedRef<'a, T> {
     #[inline]
-    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T {
+    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T
+    where
+        'a: 'k,
+    {
         check_id(key.id(), self.key_id);
         unsafe { self.ptr.as_ref() }
     }
 
-    #[inline]
-    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut T {
-        check_id(key.id(), self.key_id);
-        unsafe { { self.ptr }.as_mut() }
-    }
-
     #[inline]
     pub fn into_raw_parts(self) -> (NonNull<T>, KeyId) {
         (self.ptr, self.key_id)
     }
 
+    /// # Safety
+    ///
+    /// `ptr` and `key_id` must have been obtained from a previous call to
+    /// [`LockedRef::into_raw_parts()`] with the same lifetime `'a`.
     #[inline]
     pub unsafe fn from_raw_parts(ptr: NonNull<T>, key_id: KeyId) -> Self {
-        Self { ptr, key_id }
+        Self {
+            ptr,
+            key_id,
+            _marker: PhantomData,
+        }
     }
 }
 
+/// A locked [`Pin<&mut T>`](Pin).
+///
+/// The contents can only be accessed through pinned references, so a `&mut T`
+/// cannot be obtained for a `T` that is not [`Unpin`]:
+///
+/// ```compile_fail,E0277
+/// # use owning_key::{ForgettingKey, LockedPinMut};
+/// # use std::{marker::PhantomPinned, pin::pin};
+/// let mut key = ForgettingKey::new();
+/// let value = pin!(PhantomPinned);
+/// let locked: LockedPinMut<'_, PhantomPinned> = key.lock(value);
+/// let _: &mut PhantomPinned = locked.get_pin_mut(&mut key).get_mut();
+/// ```
 #[derive(Debug)]
-pub struct LockedVec<T> {
-    ptr: NonNull<T>,
-    len: usize,
-    capacity: usize,
-    key_id: KeyId,
+pub struct LockedPinMut<'a, T: ?Sized> {
+    inner: LockedMut<'a, T>,
 }
 
-impl<T> Locked for LockedVec<T> {
-    type Unlocked = Vec<T>;
+impl<'a, T: ?Sized> Locked for LockedPinMut<'a, T> {
+    type Unlocked = Pin<&'a mut T>;
 
     #[inline]
     fn key_id(&self) -> KeyId {
-        self.key_id
+        self.inner.key_id()
     }
 
     #[inline]
-    unsafe fn raw_lock<K: ?Sized + Key>(vec: Self::Unlocked, key: &K) -> Self {
-        let key_id = key.id();
-        let (len, capacity) = (vec.len(), vec.capacity());
-        let ptr = ManuallyDrop::new(vec).as_mut_ptr();
-        Self {
-            ptr: NonNull::new(ptr).unwrap(),
-            len,
-            capacity,
-            key_id,
-        }
+    unsafe fn raw_lock<K: ?Sized + Key>(r: Self::Unlocked, key: &K) -> Self {
+        let r = unsafe { Pin::into_inner_unchecked(r) };
+        let inner = unsafe { LockedMut::raw_lock(r, key) };
+        Self { inner }
     }
 
     #[inline]
     unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
-        check_id(key.id(), self.key_id);
-        unsafe { Vec::from_raw_parts(self.ptr.as_ptr(), self.len, self.capacity) }
+        let r = unsafe { self.inner.raw_unlock(key) };
+        unsafe { Pin::new_unchecked(r) }
     }
 
     #[inline]
     unsafe fn raw_clone(&self) -> Self {
-        Self { ..*self }
+        let inner = unsafe { self.inner.raw_clone() };
+        Self { inner }
     }
 }
 
-impl<T> LockedVec<T> {
+impl<'a, T: ?Sized> LockedPinMut<'a, T> {
     #[inline]
-    pub fn len(&self) -> usize {
-        self.len
+    pub fn get_pin<'k, K: ?Sized + Key>(&self, key: &'k K) -> Pin<&'k T>
+    where
+        'a: 'k,
+    {
+        unsafe { Pin::new_unchecked(self.inner.get(key)) }
     }
 
     #[inline]
-    pub fn capacity(&self) -> usize {
-        self.capacity
+    pub fn get_pin_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> Pin<&'k mut T>
+    where
+        'a: 'k,
+    {
+        unsafe { Pin::new_unchecked(self.inner.get_mut(key)) }
     }
+}
+
+/// A vector with a fixed capacity, stored inline. This is the unlocked form of
+/// a [`LockedArrayVec`].
+pub struct ArrayVec<T, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    len: usize,
+}
 
+impl<T, const N: usize> Default for ArrayVec<T, N> {
     #[inline]
-    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [T] {
-        check_id(key.id(), self.key_id);
-        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    fn default() -> Self {
+        Self::new()
     }
+}
 
+impl<T: Debug, const N: usize> Debug for ArrayVec<T, N> {
     #[inline]
-    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut [T] {
-        check_id(key.id(), self.key_id);
-        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.as_slice(), f)
     }
+}
 
+impl<T, const N: usize> Drop for ArrayVec<T, N> {
     #[inline]
-    pub fn get_buf<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [MaybeUninit<T>] {
-        check_id(key.id(), self.key_id);
-        unsafe { slice::from_raw_parts(self.ptr.as_ptr().cast(), self.capacity) }
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.as_mut_slice()) };
     }
+}
 
+impl<T, const N: usize> ArrayVec<T, N> {
     #[inline]
-    pub fn get_buf_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut [MaybeUninit<T>] {
-        check_id(key.id(), self.key_id);
-        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr().cast(), self.capacity) }
+    pub const fn new() -> Self {
+        Self {
+            buf: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+        }
     }
 
     #[inline]
-    pub fn into_raw_parts(self) -> (NonNull<T>, usize, usize, KeyId) {
-        (self.ptr, self.len, self.capacity, self.key_id)
+    pub fn len(&self) -> usize {
+        self.len
     }
 
     #[inline]
-    pub unsafe fn from_raw_parts(
-        ptr: NonNull<T>,
-        len: usize,
-        capacity: usize,
-        key_id: KeyId,
-    ) -> Self {
-        Self {
-            ptr,
-            len,
-            capacity,
-            key_id,
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.buf.as_ptr().cast(), self.len) }
+    }
+
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.buf.as_mut_ptr().cast(), self.len) }
+    }
+
+    /// Appends an element to the back of the vector, or returns it if the
+    /// vector is full.
+    #[inline]
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        match self.buf.get_mut(self.len) {
+            Some(slot) => {
+                slot.write(value);
+                self.len += 1;
+                Ok(())
+            }
+            None => Err(value),
         }
     }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        self.len = self.len.checked_sub(1)?;
+        Some(unsafe { self.buf[self.len].assume_init_read() })
+    }
 }
 
-#[derive(Debug)]
-pub struct LockedString {
-    inner: LockedVec<u8>,
+pub struct LockedArrayVec<T, const N: usize> {
+    inner: ManuallyDrop<ArrayVec<T, N>>,
+    key_id: KeyId,
 }
 
-impl Locked for LockedString {
-    type Unlocked = String;
+impl<T, const N: usize> Debug for LockedArrayVec<T, N> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockedArrayVec")
+            .field("len", &self.inner.len)
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, const N: usize> Locked for LockedArrayVec<T, N> {
+    type Unlocked = ArrayVec<T, N>;
 
     #[inline]
     fn key_id(&self) -> KeyId {
-        self.inner.key_id()
+        self.key_id
     }
 
     #[inline]
-    unsafe fn raw_lock<K: ?Sized + Key>(s: Self::Unlocked, key: &K) -> Self {
-        let vec = s.into_bytes();
-        let inner = unsafe { LockedVec::raw_lock(vec, key) };
-        Self { inner }
+    unsafe fn raw_lock<K: ?Sized + Key>(vec: Self::Unlocked, key: &K) -> Self {
+        let key_id = key.id();
+        Self {
+            inner: ManuallyDrop::new(vec),
+            key_id,
+        }
     }
 
     #[inline]
     unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
-        let vec = unsafe { self.inner.raw_unlock(key) };
-        unsafe { String::from_utf8_unchecked(vec) }
+        check_id(key.id(), self.key_id);
+        ManuallyDrop::into_inner(self.inner)
     }
 
     #[inline]
     unsafe fn raw_clone(&self) -> Self {
-        let inner = unsafe { self.inner.raw_clone() };
-        Self { inner }
+        let inner = unsafe { ptr::read(&self.inner) };
+        Self {
+            inner,
+            key_id: self.key_id,
+        }
     }
 }
 
-impl LockedString {
+impl<T, const N: usize> LockedArrayVec<T, N> {
     #[inline]
     pub fn len(&self) -> usize {
         self.inner.len()
     }
 
     #[inline]
-    pub fn capacity(&self) -> usize {
-        self.inner.capacity()
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
     }
 
     #[inline]
-    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k str {
-        let bytes = self.inner.get(key);
-        unsafe { str::from_utf8_unchecked(bytes) }
+    pub fn capacity(&self) -> usize {
+        N
     }
 
     #[inline]
-    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut str {
-        let bytes = self.inner.get_mut(key);
-        unsafe { str::from_utf8_unchecked_mut(bytes) }
+    pub fn get<K: ?Sized + Key>(&self, key: &K) -> &[T] {
+        check_id(key.id(), self.key_id);
+        self.inner.as_slice()
     }
 
     #[inline]
-    pub fn get_bytes<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [u8] {
-        self.inner.get(key)
+    pub fn get_mut<K: ?Sized + Key>(&mut self, key: &mut K) -> &mut [T] {
+        check_id(key.id(), self.key_id);
+        self.inner.as_mut_slice()
     }
 
     #[inline]
-    pub unsafe fn get_bytes_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> &'k mut [u8] {
-        self.inner.get_mut(key)
+    pub fn push<K: ?Sized + Key>(&mut self, key: &mut K, value: T) -> Result<(), T> {
+        check_id(key.id(), self.key_id);
+        self.inner.push(value)
     }
 
     #[inline]
-    pub fn get_bytes_buf<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k [MaybeUninit<u8>] {
-        self.inner.get_buf(key)
+    pub fn pop<K: ?Sized + Key>(&mut self, key: &mut K) -> Option<T> {
+        check_id(key.id(), self.key_id);
+        self.inner.pop()
     }
+}
 
-    #[inline]
-    pub unsafe fn get_bytes_buf_mut<'k, K: ?Sized + Key>(
-        &self,
-        key: &'k mut K,
-    ) -> &'k mut [MaybeUninit<u8>] {
-        self.inner.get_buf_mut(key)
-    }
+/// A value of one of two types. This is the unlocked form of a
+/// [`LockedEither`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
 
-    #[inline]
-    pub fn into_raw_parts(self) -> (NonNull<u8>, usize, usize, KeyId) {
-        self.inner.into_raw_parts()
-    }
+/// Either a locked value of type `L`, or an inline value of type `R` that is
+/// locked with the same key.
+pub struct LockedEither<L, R> {
+    inner: Either<L, ManuallyDrop<R>>,
+    key_id: KeyId,
+}
 
+impl<L: Debug, R> Debug for LockedEither<L, R> {
     #[inline]
-    pub unsafe fn from_raw_parts(
-        ptr: NonNull<u8>,
-        len: usize,
-        capacity: usize,
-        key_id: KeyId,
-    ) -> Self {
-        let inner = unsafe { LockedVec::from_raw_parts(ptr, len, capacity, key_id) };
-        Self { inner }
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("LockedEither");
+        match &self.inner {
+            Either::Left(left) => f.field("left", left).field("key_id", &self.key_id).finish(),
+            Either::Right(_) => f.field("key_id", &self.key_id).finish_non_exhaustive(),
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct LockedCString {
-    inner: LockedVec<u8>,
-}
-
-impl Locked for LockedCString {
-    type Unlocked = CString;
+impl<L: Locked, R> Locked for LockedEither<L, R> {
+    type Unlocked = Either<L::Unlocked, R>;
 
     #[inline]
     fn key_id(&self) -> KeyId {
-        self.inner.key_id()
+        self.key_id
     }
 
     #[inline]
-    unsafe fn raw_lock<K: ?Sized + Key>(s: Self::Unlocked, key: &K) -> Self {
-        let vec = s.into_bytes_with_nul();
-        let inner = unsafe { LockedVec::raw_lock(vec, key) };
-        Self { inner }
+    unsafe fn raw_lock<K: ?Sized + Key>(value: Self::Unlocked, key: &K) -> Self {
+        let inner = match value {
+            Either::Left(left) => Either::Left(unsafe { L::raw_lock(left, key) }),
+            Either::Right(right) => Either::Right(ManuallyDrop::new(right)),
+        };
+        Self {
+            inner,
+            key_id: key.id(),
+        }
     }
 
     #[inline]
     unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
-        let vec = unsafe { self.inner.raw_unlock(key) };
-        unsafe { CString::from_vec_with_nul_unchecked(vec) }
+        check_id(key.id(), self.key_id);
+        match self.inner {
+            Either::Left(left) => Either::Left(unsafe { left.raw_unlock(key) }),
+            Either::Right(right) => Either::Right(ManuallyDrop::into_inner(right)),
+        }
     }
 
     #[inline]
     unsafe fn raw_clone(&self) -> Self {
-        let inner = unsafe { self.inner.raw_clone() };
-        Self { inner }
+        let inner = match &self.inner {
+            Either::Left(left) => Either::Left(unsafe { left.raw_clone() }),
+            Either::Right(right) => Either::Right(unsafe { ptr::read(right) }),
+        };
+        Self {
+            inner,
+            key_id: self.key_id,
+        }
     }
 }
 
-impl LockedCString {
+impl<L, R> LockedEither<L, R> {
     #[inline]
-    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k CStr {
-        let bytes = self.inner.get(key);
-        unsafe { CStr::from_bytes_with_nul_unchecked(bytes) }
+    pub fn is_left(&self) -> bool {
+        matches!(self.inner, Either::Left(_))
     }
 
     #[inline]
-    pub fn into_raw_parts(self) -> (NonNull<u8>, usize, usize, KeyId) {
-        self.inner.into_raw_parts()
+    pub fn is_right(&self) -> bool {
+        matches!(self.inner, Either::Right(_))
     }
 
     #[inline]
-    pub unsafe fn from_raw_parts(
-        ptr: NonNull<u8>,
-        len: usize,
-        capacity: usize,
-        key_id: KeyId,
-    ) -> Self {
-        let inner = unsafe { LockedVec::from_raw_parts(ptr, len, capacity, key_id) };
-        Self { inner }
+    pub fn left(&self) -> Option<&L> {
+        match &self.inner {
+            Either::Left(left) => Some(left),
+            Either::Right(_) => None,
+        }
+    }
+
+    #[inline]
+    pub fn left_mut(&mut self) -> Option<&mut L> {
+        match &mut self.inner {
+            Either::Left(left) => Some(left),
+            Either::Right(_) => None,
+        }
+    }
+
+    #[inline]
+    pub fn right<'k, K: ?Sized + Key>(&'k self, key: &'k K) -> Option<&'k R> {
+        check_id(key.id(), self.key_id);
+        match &self.inner {
+            Either::Left(_) => None,
+            Either::Right(right) => Some(right),
+        }
+    }
+
+    #[inline]
+    pub fn right_mut<'k, K: ?Sized + Key>(&'k mut self, key: &'k mut K) -> Option<&'k mut R> {
+        check_id(key.id(), self.key_id);
+        match &mut self.inner {
+            Either::Left(_) => None,
+            Either::Right(right) => Some(right),
+        }
     }
 }
 
+/// An optional locked value, which keeps its [`KeyId`] even when empty.
 #[derive(Debug)]
-pub struct LockedRc<T: ?Sized> {
-    ptr: NonNull<T>,
+pub struct LockedOption<L> {
+    inner: Option<L>,
     key_id: KeyId,
 }
 
-impl<T: ?Sized> Locked for LockedRc<T> {
-    type Unlocked = Rc<T>;
+impl<L: Locked> Locked for LockedOption<L> {
+    type Unlocked = Option<L::Unlocked>;
 
     #[inline]
     fn key_id(&self) -> KeyId {
@@ -417,117 +649,253 @@ impl<T: ?Sized> Locked for LockedRc<T> {
     }
 
     #[inline]
-    unsafe fn raw_lock<K: ?Sized + Key>(rc: Self::Unlocked, key: &K) -> Self {
-        let key_id = key.id();
-        let ptr = NonNull::new(Rc::into_raw(rc) as *mut T).unwrap();
-        Self { ptr, key_id }
+    unsafe fn raw_lock<K: ?Sized + Key>(value: Self::Unlocked, key: &K) -> Self {
+        let inner = value.map(|value| unsafe { L::raw_lock(value, key) });
+        Self {
+            inner,
+            key_id: key.id(),
+        }
     }
 
     #[inline]
     unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
         check_id(key.id(), self.key_id);
-        unsafe { Rc::from_raw(self.ptr.as_ptr()) }
+        self.inner.map(|value| unsafe { value.raw_unlock(key) })
     }
 
     #[inline]
     unsafe fn raw_clone(&self) -> Self {
-        Self { ..*self }
+        let inner = self
+            .inner
+            .as_ref()
+            .map(|value| unsafe { value.raw_clone() });
+        Self {
+            inner,
+            key_id: self.key_id,
+        }
     }
 }
 
-impl<T: ?Sized> LockedRc<T> {
+impl<L> LockedOption<L> {
     #[inline]
-    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T {
-        check_id(key.id(), self.key_id);
-        unsafe { self.ptr.as_ref() }
+    pub fn is_some(&self) -> bool {
+        self.inner.is_some()
     }
 
     #[inline]
-    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> Option<&'k mut T> {
-        check_id(key.id(), self.key_id);
-        let mut rc = ManuallyDrop::new(unsafe { Rc::from_raw(self.ptr.as_ptr()) });
-        let ptr: *mut T = Rc::get_mut(&mut rc)?;
-        unsafe { Some(&mut *ptr) }
+    pub fn is_none(&self) -> bool {
+        self.inner.is_none()
     }
 
     #[inline]
-    pub fn clone<K: ?Sized + Key>(&self, key: &K) -> Rc<T> {
-        check_id(key.id(), self.key_id);
-        unsafe {
-            Rc::increment_strong_count(self.ptr.as_ptr());
-            Rc::from_raw(self.ptr.as_ptr())
-        }
+    pub fn as_ref(&self) -> Option<&L> {
+        self.inner.as_ref()
     }
+}
 
+impl<L: AccessLocked> LockedOption<L> {
     #[inline]
-    pub fn downgrade<K: ?Sized + Key>(&self, key: &K) -> rc::Weak<T> {
+    pub fn get<'k, K: ?Sized + Key>(&'k self, key: &'k K) -> Option<&'k L::AccessTarget> {
         check_id(key.id(), self.key_id);
-        let rc = ManuallyDrop::new(unsafe { Rc::from_raw(self.ptr.as_ptr()) });
-        Rc::downgrade(&rc)
+        Some(self.inner.as_ref()?.access(key))
     }
-}
 
-#[derive(Debug)]
-pub struct LockedArc<T: ?Sized> {
-    ptr: NonNull<T>,
-    key_id: KeyId,
+    #[inline]
+    pub fn get_mut<'k, K: ?Sized + Key>(
+        &'k mut self,
+        key: &'k mut K,
+    ) -> Option<&'k mut L::AccessTarget> {
+        check_id(key.id(), self.key_id);
+        Some(self.inner.as_mut()?.access_mut(key))
+    }
 }
 
-impl<T: ?Sized> Locked for LockedArc<T> {
-    type Unlocked = Arc<T>;
+impl<T: ?Sized> AccessLocked for LockedMut<'_, T> {
+    type AccessTarget = T;
 
     #[inline]
-    fn key_id(&self) -> KeyId {
-        self.key_id
+    fn access<'k, K: ?Sized + Key>(&'k self, key: &'k K) -> &'k T {
+        self.get(key)
     }
 
     #[inline]
-    unsafe fn raw_lock<K: ?Sized + Key>(arc: Self::Unlocked, key: &K) -> Self {
-        let key_id = key.id();
-        let ptr = NonNull::new(Arc::into_raw(arc) as *mut T).unwrap();
-        Self { ptr, key_id }
+    fn access_mut<'k, K: ?Sized + Key>(&'k mut self, key: &'k mut K) -> &'k mut T {
+        self.get_mut(key)
     }
+}
+
+impl<T, const N: usize> AccessLocked for LockedArrayVec<T, N> {
+    type AccessTarget = [T];
 
     #[inline]
-    unsafe fn raw_unlock<K: ?Sized + Key>(self, key: &mut K) -> Self::Unlocked {
-        check_id(key.id(), self.key_id);
-        unsafe { Arc::from_raw(self.ptr.as_ptr()) }
+    fn access<'k, K: ?Sized + Key>(&'k self, key: &'k K) -> &'k [T] {
+        self.get(key)
     }
 
     #[inline]
-    unsafe fn raw_clone(&self) -> Self {
-        Self { ..*self }
+    fn access_mut<'k, K: ?Sized + Key>(&'k mut self, key: &'k mut K) -> &'k mut [T] {
+        self.get_mut(key)
     }
 }
 
-impl<T: ?Sized> LockedArc<T> {
+#[cfg(feature = "serde")]
+impl<T: ?Sized + Serialize> SerializeLocked for LockedMut<'_, T> {
     #[inline]
-    pub fn get<'k, K: ?Sized + Key>(&self, key: &'k K) -> &'k T {
-        check_id(key.id(), self.key_id);
-        unsafe { self.ptr.as_ref() }
+    fn serialize_locked<K: ?Sized + Key, S: Serializer>(
+        &self,
+        key: &K,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.get(key).serialize(serializer)
     }
+}
 
+#[cfg(feature = "serde")]
+impl<T: Serialize, const N: usize> SerializeLocked for LockedArrayVec<T, N> {
     #[inline]
-    pub fn get_mut<'k, K: ?Sized + Key>(&self, key: &'k mut K) -> Option<&'k mut T> {
-        check_id(key.id(), self.key_id);
-        let mut arc = ManuallyDrop::new(unsafe { Arc::from_raw(self.ptr.as_ptr()) });
-        let ptr: *mut T = Arc::get_mut(&mut arc)?;
-        unsafe { Some(&mut *ptr) }
+    fn serialize_locked<K: ?Sized + Key, S: Serializer>(
+        &self,
+        key: &K,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.get(key).serialize(serializer)
     }
+}
 
-    #[inline]
-    pub fn clone<K: ?Sized + Key>(&self, key: &K) -> Arc<T> {
-        check_id(key.id(), self.key_id);
-        unsafe {
-            Arc::increment_strong_count(self.ptr.as_ptr());
-            Arc::from_raw(self.ptr.as_ptr())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ForgettingKey;
+    use core::{marker::PhantomPinned, pin::pin};
+    #[cfg(feature = "std")]
+    use std::string::ToString;
+
+    #[cfg(not(feature = "std"))]
+    #[test]
+    #[should_panic(expected = "locked value accessed with wrong key")]
+    fn wrong_key_panics() {
+        let mut x = 1;
+        let key = ForgettingKey::new();
+        let m: LockedMut<'_, i32> = key.lock(&mut x);
+        m.get(&ForgettingKey::new());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn wrong_key_panic_payload() {
+        let mut x = 1;
+        let key = ForgettingKey::new();
+        let other = ForgettingKey::new();
+        let m: LockedMut<'_, i32> = key.lock(&mut x);
+        let err = catch_wrong_key(|| m.get(&other));
+        assert_eq!(err.expected, key.id());
+        assert_eq!(err.got, other.id());
+        assert!(err
+            .to_string()
+            .starts_with("locked value accessed with wrong key"));
+    }
+
+    #[test]
+    fn wrong_key_error() {
+        let mut x = 1;
+        let key = ForgettingKey::new();
+        let other = ForgettingKey::new();
+        let m: LockedMut<'_, i32> = key.lock(&mut x);
+        let err = m.try_get(&other).unwrap_err();
+        assert_eq!(err.expected, key.id());
+        assert_eq!(err.got, other.id());
+        assert_eq!(*m.try_get(&key).unwrap(), 1);
+    }
+
+    #[test]
+    fn array_vec_push_to_capacity() {
+        let mut key = ForgettingKey::new();
+        let mut v: LockedArrayVec<i32, 3> = key.lock(ArrayVec::new());
+        assert!(v.is_empty());
+        assert_eq!(v.capacity(), 3);
+        for i in 0..3 {
+            assert_eq!(v.push(&mut key, i), Ok(()));
+        }
+        assert_eq!(v.push(&mut key, 3), Err(3));
+        assert_eq!(v.len(), 3);
+        assert_eq!(v.get(&key), [0, 1, 2]);
+        v.get_mut(&mut key)[1] = 4;
+        assert_eq!(v.pop(&mut key), Some(2));
+        assert_eq!(key.unlock(v).as_slice(), [0, 4]);
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "std"),
+        should_panic(expected = "locked value accessed with wrong key")
+    )]
+    fn array_vec_wrong_key_panics() {
+        let key = ForgettingKey::new();
+        let mut other = ForgettingKey::new();
+        let mut v: LockedArrayVec<i32, 1> = key.lock(ArrayVec::new());
+        #[cfg(feature = "std")]
+        assert_eq!(catch_wrong_key(|| v.push(&mut other, 0)).got, other.id());
+        #[cfg(not(feature = "std"))]
+        let _ = v.push(&mut other, 0);
+    }
+
+    #[test]
+    fn slice_len() {
+        let mut a = [1, 2, 3];
+        let mut b: [i32; 0] = [];
+        let key = ForgettingKey::new();
+        let a: LockedMut<'_, [i32]> = key.lock(&mut a[..]);
+        let b: LockedMut<'_, [i32]> = key.lock(&mut b[..]);
+        assert_eq!((a.len(), a.is_empty()), (3, false));
+        assert_eq!((b.len(), b.is_empty()), (0, true));
+    }
+
+    #[test]
+    fn pin_mut_keeps_pin() {
+        struct Pinned {
+            value: i32,
+            _pin: PhantomPinned,
         }
-    }
 
-    #[inline]
-    pub fn downgrade<K: ?Sized + Key>(&self, key: &K) -> sync::Weak<T> {
-        check_id(key.id(), self.key_id);
-        let arc = ManuallyDrop::new(unsafe { Arc::from_raw(self.ptr.as_ptr()) });
-        Arc::downgrade(&arc)
+        let mut key = ForgettingKey::new();
+        let value = pin!(Pinned {
+            value: 1,
+            _pin: PhantomPinned,
+        });
+        let addr: *const Pinned = &*value;
+        let locked: LockedPinMut<'_, Pinned> = key.lock(value);
+        let pinned: Pin<&Pinned> = locked.get_pin(&key);
+        assert_eq!(pinned.value, 1);
+        assert!(ptr::eq(pinned.get_ref(), addr));
+        let pinned: Pin<&mut Pinned> = locked.get_pin_mut(&mut key);
+        unsafe { pinned.get_unchecked_mut().value = 2 };
+        let value: Pin<&mut Pinned> = key.unlock(locked);
+        assert_eq!(value.value, 2);
+        assert!(ptr::eq(&*value, addr));
+    }
+
+    #[test]
+    fn either_both_variants() {
+        let mut x = 1;
+        let mut key = ForgettingKey::new();
+        let left: LockedEither<LockedMut<'_, i32>, i32> = key.lock(Either::Left(&mut x));
+        assert!(left.is_left());
+        assert_eq!(left.right(&key), None);
+        let inner = left.left().unwrap();
+        assert_eq!(inner.key_id(), key.id());
+        *inner.get_mut(&mut key) += 1;
+        #[cfg(feature = "std")]
+        {
+            let other = ForgettingKey::new();
+            assert_eq!(catch_wrong_key(|| inner.get(&other)).got, other.id());
+        }
+        assert!(matches!(key.unlock(left), Either::Left(&mut 2)));
+
+        let mut right: LockedEither<LockedMut<'_, i32>, i32> = key.lock(Either::Right(3));
+        assert!(right.is_right());
+        assert!(right.left().is_none());
+        *right.right_mut(&mut key).unwrap() += 1;
+        assert_eq!(right.right(&key), Some(&4));
+        assert_eq!(key.unlock(right), Either::Right(4));
     }
 }
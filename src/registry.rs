@@ -0,0 +1,185 @@
+use crate::{ForgettingKey, KeyId, Locked};
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::{
+    any::{Any, TypeId},
+    fmt::{self, Debug, Formatter},
+};
+
+/// A locked value of any type, for use as a trait object.
+pub trait AnyLocked: Any {
+    fn locked_key_id(&self) -> KeyId;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<L: Locked + Any> AnyLocked for L {
+    #[inline]
+    fn locked_key_id(&self) -> KeyId {
+        self.key_id()
+    }
+
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    #[inline]
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Debug for dyn AnyLocked {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnyLocked")
+            .field("key_id", &self.key_id())
+            .finish_non_exhaustive()
+    }
+}
+
+impl dyn AnyLocked {
+    /// Returns the [`KeyId`] of the key used to create this value.
+    #[inline]
+    pub fn key_id(&self) -> KeyId {
+        self.locked_key_id()
+    }
+
+    #[inline]
+    pub fn downcast_ref<L: Locked + Any>(&self) -> Option<&L> {
+        self.as_any().downcast_ref()
+    }
+
+    #[inline]
+    pub fn downcast_mut<L: Locked + Any>(&mut self) -> Option<&mut L> {
+        self.as_any_mut().downcast_mut()
+    }
+
+    #[inline]
+    pub fn downcast<L: Locked + Any>(self: Box<Self>) -> Result<Box<L>, Box<Self>> {
+        if self.as_any().is::<L>() {
+            Ok(self.into_any().downcast().unwrap())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+type LockFn = fn(&ForgettingKey, Box<dyn Any>) -> Box<dyn AnyLocked>;
+
+/// A registry of locked types, which locks values whose types are only known at
+/// runtime.
+///
+/// Each locked type `L` is registered under its unlocked type
+/// `L::Unlocked`, so at most one locked type can be registered for each
+/// unlocked type.
+#[derive(Debug, Default)]
+pub struct LockRegistry {
+    lock_fns: BTreeMap<TypeId, LockFn>,
+}
+
+impl LockRegistry {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `L`, so that values of type `L::Unlocked` are locked as `L`.
+    /// Returns `false` if a locked type was already registered for
+    /// `L::Unlocked`, in which case it is replaced.
+    #[inline]
+    pub fn register<L: Locked + Any>(&mut self) -> bool
+    where
+        L::Unlocked: Any,
+    {
+        let lock_fn: LockFn = |key, value| {
+            let Ok(value) = value.downcast::<L::Unlocked>() else {
+                unreachable!("value should have the registered type");
+            };
+            Box::new(key.lock::<L>(*value))
+        };
+        self.lock_fns
+            .insert(TypeId::of::<L::Unlocked>(), lock_fn)
+            .is_none()
+    }
+
+    #[inline]
+    pub fn is_registered(&self, type_id: TypeId) -> bool {
+        self.lock_fns.contains_key(&type_id)
+    }
+
+    /// Locks `value` as the locked type registered for its type, or returns it
+    /// back if no locked type is registered.
+    #[inline]
+    pub fn lock(
+        &self,
+        key: &ForgettingKey,
+        value: Box<dyn Any>,
+    ) -> Result<Box<dyn AnyLocked>, Box<dyn Any>> {
+        match self.lock_fns.get(&(*value).type_id()) {
+            Some(lock_fn) => Ok(lock_fn(key, value)),
+            None => Err(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, LockedString, LockedVec};
+    use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+
+    fn registry() -> LockRegistry {
+        let mut registry = LockRegistry::new();
+        assert!(registry.register::<LockedVec<u8>>());
+        assert!(registry.register::<LockedString>());
+        registry
+    }
+
+    #[test]
+    fn lock_registered_types() {
+        let registry = registry();
+        let mut key = ForgettingKey::new();
+        let bytes = registry.lock(&key, Box::new(vec![1u8, 2])).unwrap();
+        let string = registry.lock(&key, Box::new("a".to_owned())).unwrap();
+        assert_eq!(bytes.key_id(), key.id());
+        assert_eq!(string.key_id(), key.id());
+        assert!(bytes.downcast_ref::<LockedString>().is_none());
+        let locked = bytes.downcast_ref::<LockedVec<u8>>().unwrap();
+        assert_eq!(locked.get(&key), [1, 2]);
+
+        let bytes = bytes.downcast::<LockedVec<u8>>().unwrap();
+        assert_eq!(key.unlock(*bytes), [1, 2]);
+        let string = string.downcast::<LockedString>().unwrap();
+        assert_eq!(key.unlock(*string), "a");
+    }
+
+    #[test]
+    fn lock_unregistered_type() {
+        let registry = registry();
+        let key = ForgettingKey::new();
+        assert!(!registry.is_registered(TypeId::of::<Vec<i32>>()));
+        let value = registry.lock(&key, Box::new(vec![1i32])).unwrap_err();
+        assert_eq!(*value.downcast::<Vec<i32>>().unwrap(), [1]);
+    }
+
+    #[test]
+    fn register_replaces() {
+        let mut registry = registry();
+        assert!(!registry.register::<LockedString>());
+        assert!(registry.is_registered(TypeId::of::<String>()));
+        let mut key = ForgettingKey::new();
+        let string = registry.lock(&key, Box::new("a".to_owned())).unwrap();
+        let string = string.downcast::<LockedString>().unwrap();
+        assert_eq!(key.unlock(*string), "a");
+    }
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "derive")]
+
+use owning_key::{ForgettingKey, Key, Locked, LockedBox, LockedVec, WrongKeyError};
+use std::panic::{self, AssertUnwindSafe};
+
+#[derive(Locked)]
+struct Named {
+    a: LockedBox<i32>,
+    b: LockedVec<u8>,
+}
+
+#[derive(Locked)]
+struct Tuple<T>(LockedBox<T>, LockedVec<u8>);
+
+#[derive(Locked)]
+#[locked(unlocked = Parts)]
+struct Generic<T> {
+    value: LockedBox<T>,
+    values: LockedVec<T>,
+}
+
+#[test]
+fn named_round_trip() {
+    let mut key = ForgettingKey::new();
+    let locked: Named = key.lock(NamedUnlocked {
+        a: Box::new(1),
+        b: vec![2, 3],
+    });
+    assert_eq!(locked.key_id(), key.id());
+    *locked.a.get_mut(&mut key) += 1;
+    locked.b.get_mut(&mut key)[0] = 4;
+    let NamedUnlocked { a, b } = key.unlock(locked);
+    assert_eq!(*a, 2);
+    assert_eq!(b, [4, 3]);
+}
+
+#[test]
+fn tuple_round_trip() {
+    let mut key = ForgettingKey::new();
+    let locked: Tuple<i32> = key.lock(TupleUnlocked(Box::new(1), vec![2]));
+    assert_eq!(*locked.0.get(&key), 1);
+    assert_eq!(locked.1.get(&key), [2]);
+    let TupleUnlocked(a, b) = key.unlock(locked);
+    assert_eq!((*a, b), (1, vec![2]));
+}
+
+#[test]
+fn generic_round_trip() {
+    let mut key = ForgettingKey::new();
+    let locked: Generic<String> = key.lock(Parts {
+        value: Box::new("a".to_owned()),
+        values: vec!["b".to_owned()],
+    });
+    locked.values.get_mut(&mut key)[0].push('c');
+    let clone = unsafe { locked.raw_clone() };
+    assert_eq!(clone.values.get(&key), ["bc"]);
+    let Parts { value, values } = key.unlock(locked);
+    assert_eq!(*value, "a");
+    assert_eq!(values, ["bc"]);
+}
+
+#[test]
+fn wrong_key_panics() {
+    // Neither field has an allocation to leak when the unlock panics.
+    let key = ForgettingKey::new();
+    let mut other = ForgettingKey::new();
+    let locked: Tuple<()> = key.lock(TupleUnlocked(Box::new(()), Vec::new()));
+    let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| other.unlock(locked))) else {
+        panic!("expected a panic");
+    };
+    let err = payload.downcast::<WrongKeyError>().unwrap();
+    assert_eq!((err.expected, err.got), (key.id(), other.id()));
+}
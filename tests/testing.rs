@@ -0,0 +1,25 @@
+#![cfg(feature = "testing")]
+
+// This is the only test in its binary, since resetting the counter while other
+// tests create keys would break their uniqueness.
+
+use owning_key::KeyId;
+use std::thread;
+
+#[test]
+fn reset_counter_for_testing() {
+    // Reserve a block of ids for this thread, then use only part of it.
+    let _ = (KeyId::new(), KeyId::new());
+
+    // The reset also discards the rest of this thread's block.
+    unsafe { KeyId::reset_counter_for_testing() };
+    let ids: Vec<String> = (0..3).map(|_| KeyId::new().to_string()).collect();
+    assert_eq!(ids, ["#0", "#1", "#2"]);
+
+    // A new thread reserves the next block of 256 ids after this thread's.
+    let id = thread::spawn(KeyId::new).join().unwrap();
+    assert_eq!(id.to_string(), "#256");
+
+    unsafe { KeyId::reset_counter_for_testing() };
+    assert_eq!(KeyId::new().to_string(), "#0");
+}